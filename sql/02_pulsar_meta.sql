@@ -0,0 +1,13 @@
+create table if not exists pulsar_meta (
+    id serial primary key,
+	alias text not null unique,
+	j_name text,
+	b_name text,
+	j2000_ra text,
+	j2000_dec text,
+	master_parfile_id integer
+);
+alter table pulsar_meta add column if not exists created_at timestamptz
+    default (now());
+alter table pulsar_meta add column if not exists pending_review boolean
+    not null default false;
\ No newline at end of file
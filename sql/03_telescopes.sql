@@ -9,6 +9,8 @@ create table if not exists telescopes (
 
     -- long/lat, datum? 
 );
+alter table telescopes add column if not exists created_at timestamptz
+    default (now());
 insert into telescopes 
 (name,          itrf_x,     itrf_y,     itrf_z,         abbreviation,   code) values
 ('effelsberg',  4033949.5,  486989.4,   4900430.8,      'eff',          'g'),
@@ -25,6 +27,8 @@ create table if not exists obs_systems(
     clock text not null,
     code text not null
 );
+alter table obs_systems add column if not exists created_at timestamptz
+    default (now());
 insert into obs_systems 
 (name,       telescope_id,  frontend,   backend,    clock,              code) values
 ('eff_rfsoc_p217',      1,  'p217',     'rfsoc',    'unknown',           'g'),
@@ -0,0 +1,14 @@
+create table if not exists par_meta (
+    id serial primary key,
+    pulsar_id int references pulsar_meta,
+    checksum UUID,
+    file_path text
+);
+alter table par_meta add column if not exists created_at timestamptz
+    default (now());
+alter table par_meta add column if not exists psr_name text;
+alter table par_meta add column if not exists f0 double precision;
+alter table par_meta add column if not exists f1 double precision;
+alter table par_meta add column if not exists dm double precision;
+alter table par_meta add column if not exists pepoch double precision;
+alter table par_meta add column if not exists binary_model text;
\ No newline at end of file
@@ -3,4 +3,6 @@ create table if not exists template_meta (
     pulsar_id int references pulsar_meta,
     checksum UUID,
     file_path text
-);
\ No newline at end of file
+);
+alter table template_meta add column if not exists created_at timestamptz
+    default (now());
\ No newline at end of file
@@ -4,4 +4,6 @@ create table if not exists raw_meta (
     checksum UUID,
     pulsar_id integer references pulsar_meta,
     observer_id integer 
-);
\ No newline at end of file
+);
+alter table raw_meta add column if not exists created_at timestamptz
+    default (now());
\ No newline at end of file
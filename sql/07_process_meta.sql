@@ -7,4 +7,6 @@ create table if not exists process_meta (
     n_subints smallint,
     method text,
     user_id integer references users
-);
\ No newline at end of file
+);
+alter table process_meta add column if not exists created_at timestamptz
+    default (now());
\ No newline at end of file
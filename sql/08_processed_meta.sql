@@ -0,0 +1,8 @@
+create table if not exists processed_meta (
+    id serial primary key,
+    process_id integer references process_meta,
+    checksum UUID,
+    file_path text
+);
+alter table processed_meta add column if not exists created_at timestamptz
+    default (now());
\ No newline at end of file
@@ -11,4 +11,8 @@ create table if not exists toas (
     toa_frac double precision not null,
     toa_err real,
     frequency real not null
-);
\ No newline at end of file
+);
+alter table toas add column if not exists created_at timestamptz
+    default (now());
+alter table toas add column if not exists subint integer;
+alter table toas add column if not exists chan integer;
\ No newline at end of file
@@ -0,0 +1,25 @@
+create table if not exists diag_floats (
+    id serial primary key,
+    process integer,
+    diagnostic text,
+    result float
+);
+alter table diag_floats add column if not exists created_at timestamptz
+    default (now());
+create table if not exists diag_plots (
+    id serial primary key,
+    process integer,
+    diagnostic text,
+    filepath text
+);
+alter table diag_plots add column if not exists created_at timestamptz
+    default (now());
+create table if not exists diag_values (
+    id serial primary key,
+    process integer,
+    diagnostic text,
+    key text,
+    result float
+);
+alter table diag_values add column if not exists created_at timestamptz
+    default (now());
\ No newline at end of file
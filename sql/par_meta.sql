@@ -1,6 +0,0 @@
-create table if not exists par_meta (
-    id serial primary key,
-    pulsar_id int references pulsar_meta,
-    checksum UUID,
-    file_path text
-);
\ No newline at end of file
@@ -1,100 +1,266 @@
 //! A module to handle the postgres database connection.
 //! All DB interactions should pass through Archivist.
 //!
-//! Every function modifying the DB (i.e. not ones that only _get_ data) will
-//! automatically start a transaction if it there is not already one active.
-//! No function should commit a transaction, except for `commit_transcation`.
+//! Every function modifying the DB (i.e. not ones that only _get_ data) runs
+//! inside a transaction. If one has been started explicitly, it is used and
+//! only `commit_transaction` will commit it. Otherwise the operation gets an
+//! implicit transaction of its own, which is committed as soon as the
+//! operation succeeds, and rolled back if it fails.
 
-use crate::{ARPAError, config::Config};
-use log::{info, warn};
-use std::{fmt::Debug, fs::read_to_string};
+use crate::{
+    ARPAError,
+    config::{ChecksumAlgorithm, Config},
+    conveniences::{NoProgress, assert_file_exists, compute_checksum},
+    diagnostics::DiagnosticRegistry,
+};
+use futures_util::{Stream, StreamExt};
+use log::{debug, info, warn};
+use serde::Serialize;
+use std::{
+    any::TypeId,
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Debug,
+    fs::read_to_string,
+    sync::{Arc, LazyLock, Mutex, PoisonError},
+};
 
 pub mod data_types;
 mod error;
+mod import;
+mod migrations;
+mod provenance;
+mod quality;
+mod readiness;
+mod review;
+mod search;
 pub mod table;
+mod tim_export;
+mod value;
+mod verification;
 
+use data_types::{Checksum, RawMeta, User};
 pub use error::ArchivistError;
+pub use import::{ImportOutcome, ImportReport};
+pub use provenance::ToaProvenance;
+pub use quality::DiagnosticStats;
+pub use readiness::{ReadinessCheck, ReadinessReport};
+pub use review::AutoAddEvent;
 use sqlx::{
-    FromRow, PgConnection, Pool, Postgres, Transaction,
+    FromRow, PgConnection, PgExecutor, Pool, Postgres, Row, Transaction,
     postgres::{PgPoolOptions, PgRow},
+    types::time,
 };
-use table::{Table, TableItem};
+use table::{ChecksumItem, FileItem, InsertBinds, Table, TableItem};
+use tokio::task::JoinSet;
+pub use value::SqlValue;
+pub use verification::{VerificationHandle, VerificationProgress};
 
 type Result<T> = std::result::Result<T, ArchivistError>;
 
+/// Postgres allows at most this many parameters per query.
+const MAX_PARAMETERS: usize = 65_535;
+
+/// How many rows [`Archivist::get_all`] may get before warning.
+const LARGE_TABLE: usize = 100_000;
+
+/// The contents of `sql/`, with their names, for [`Archivist::new_embedded`].
+/// Tables are listed after the ones they reference.
+const EMBEDDED_SQL: [(&str, &str); 10] = [
+    ("01_users.sql", include_str!("../sql/01_users.sql")),
+    (
+        "02_pulsar_meta.sql",
+        include_str!("../sql/02_pulsar_meta.sql"),
+    ),
+    (
+        "03_telescopes.sql",
+        include_str!("../sql/03_telescopes.sql"),
+    ),
+    ("04_par_meta.sql", include_str!("../sql/04_par_meta.sql")),
+    (
+        "05_template_meta.sql",
+        include_str!("../sql/05_template_meta.sql"),
+    ),
+    ("06_raw_meta.sql", include_str!("../sql/06_raw_meta.sql")),
+    (
+        "07_process_meta.sql",
+        include_str!("../sql/07_process_meta.sql"),
+    ),
+    (
+        "08_processed_meta.sql",
+        include_str!("../sql/08_processed_meta.sql"),
+    ),
+    ("09_toas.sql", include_str!("../sql/09_toas.sql")),
+    (
+        "10_diagnostics.sql",
+        include_str!("../sql/10_diagnostics.sql"),
+    ),
+];
+
 /// This keeps a live connection to the database and acts as your friend in
 /// getting and posting data.
 ///
-/// For any queries that modify the DB, a transaction
-/// _will_ be used, and if the user has not explicitly started one, a warning
-/// will be issued (though not an error). NB: If a transaction goes out of scope,
-/// it is rolled back.
+/// For any queries that modify the DB, a transaction _will_ be used. If the
+/// user has not explicitly started one, each such query is committed on its
+/// own (auto-commit). NB: If an explicit transaction goes out of scope, e.g.
+/// when the `Archivist` is dropped, it is rolled back.
 ///
 /// All tables are accessible _only_ through the `Table` enum.
 pub struct Archivist {
     pool: Pool<Postgres>,
-    config: Config,
+    /// Shared, so that work running off the runtime can read it too.
+    config: Arc<Config>,
 
     /// This is here so that potentially destructive app commands always go
     /// through transactions.
     current_transaction: Option<Transaction<'static, Postgres>>,
+    /// Whether `current_transaction` was started implicitly, by a single
+    /// operation. If it is still around after that operation, it failed.
+    implicit: bool,
+    /// Called when something is added to the registry automatically.
+    on_auto_add: Option<review::AutoAddHook>,
+    /// The diagnostics that can be run on cooked files.
+    diagnostics: DiagnosticRegistry,
 }
 
 impl Archivist {
-    /// Initializes a new connection to the database.
+    /// Initializes a new connection to the database, setting it up with the
+    /// SQL files in `sql_setup_dirs`. The directories are applied in the
+    /// order given, e.g. the crate's `sql/` first and then any site-specific
+    /// extensions, and the files in each in order of their names.
+    ///
+    /// Each file is a migration, applied only once and recorded by name in
+    /// `schema_migrations`. Files must therefore have unique names across all
+    /// the directories, and schema changes go in new files rather than edits
+    /// of applied ones.
+    ///
+    /// The configured diagnostics must all be in `diagnostics`, e.g.
+    /// [`DiagnosticRegistry::default`] with the application's own registered.
     ///
     /// # Errors
-    /// Fails if setup data is missing. Forwards errors from `sqlx`.
+    /// Fails if setup data is missing, several files have the same name, or
+    /// a configured diagnostic is unknown. Forwards errors from `sqlx`.
     pub async fn new(
         config_path: impl AsRef<std::path::Path>,
-        sql_setup_dir: impl AsRef<std::path::Path>,
+        sql_setup_dirs: &[impl AsRef<std::path::Path> + Sync],
+        diagnostics: DiagnosticRegistry,
     ) -> std::result::Result<Self, ARPAError> {
         info!("Reading config \"{}\"...", config_path.as_ref().display());
         let config = Config::load(config_path)?;
+        config.validate_with(&diagnostics)?;
 
-        let pool = PgPoolOptions::new()
-            .max_connections(config.database.pool_connections)
-            .acquire_timeout(std::time::Duration::from_millis(
-                config.database.connection_timeout,
-            ))
-            .connect(&config.database.url)
-            .await
-            .map_err(ArchivistError::from)?;
+        // Setup from sql directories
+        let mut files = Vec::new();
+        for dir in sql_setup_dirs {
+            info!("Reading setup dir \"{}\"...", dir.as_ref().display());
+            let mut paths = std::fs::read_dir(dir)?
+                .map(|entry| entry.map(|e| e.path()))
+                .collect::<std::io::Result<Vec<_>>>()?;
+            paths.sort();
 
-        info!("Connected to database!");
-
-        // Setup from sql directory
-        info!(
-            "Reading setup dir \"{}\"...",
-            sql_setup_dir.as_ref().display()
-        );
-        let files = std::fs::read_dir(sql_setup_dir)?
-            .flat_map(|entry| entry.map(|e| read_to_string(e.path())))
-            .flatten()
-            .collect::<Vec<_>>();
-
-        for file in files {
-            for sql in file.split(';') {
-                sqlx::query(sql)
-                    .execute(&pool)
-                    .await
-                    .map_err(ArchivistError::from)?;
+            for path in paths.iter().filter(|p| p.is_file()) {
+                let name = path
+                    .file_name()
+                    .map(|n| n.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                files.push((name, read_to_string(path)?));
             }
         }
+
+        Self::connect(
+            config,
+            diagnostics,
+            files
+                .iter()
+                .map(|(name, sql)| (name.as_str(), sql.as_str())),
+        )
+        .await
+    }
+
+    /// Same as [`Self::new`], but sets up the database with the SQL that
+    /// was embedded in the crate at compile time, so no setup files are
+    /// needed at runtime.
+    ///
+    /// # Errors
+    /// Fails if the config can't be read, or a configured diagnostic is
+    /// unknown. Forwards errors from `sqlx`.
+    pub async fn new_embedded(
+        config_path: impl AsRef<std::path::Path>,
+        diagnostics: DiagnosticRegistry,
+    ) -> std::result::Result<Self, ARPAError> {
+        info!("Reading config \"{}\"...", config_path.as_ref().display());
+        let config = Config::load(config_path)?;
+        config.validate_with(&diagnostics)?;
+
+        info!("Using embedded setup...");
+        Self::connect(config, diagnostics, EMBEDDED_SQL.into_iter()).await
+    }
+
+    /// Connects to the database and applies the `setup` migrations, given as
+    /// file name and contents.
+    async fn connect<'a>(
+        config: Config,
+        diagnostics: DiagnosticRegistry,
+        setup: impl Iterator<Item = (&'a str, &'a str)>,
+    ) -> std::result::Result<Self, ARPAError> {
+        let pool = Self::connect_pool(&config).await?;
+
+        info!("Connected to database!");
+
+        migrations::run_migrations(&pool, setup).await?;
         info!("Finished setup!");
 
         Ok(Self {
             pool,
-            config,
+            config: Arc::new(config),
             current_transaction: None,
+            implicit: false,
+            on_auto_add: None,
+            diagnostics,
         })
     }
 
+    /// Connects a pool to the database, retrying with exponential backoff
+    /// as configured.
+    async fn connect_pool(config: &Config) -> Result<Pool<Postgres>> {
+        let database = &config.database;
+        let mut options = PgPoolOptions::new()
+            .max_connections(database.pool_connections)
+            .min_connections(database.min_connections)
+            .acquire_timeout(std::time::Duration::from_millis(
+                database.connection_timeout,
+            ));
+        if let Some(ms) = database.idle_timeout_ms {
+            options =
+                options.idle_timeout(std::time::Duration::from_millis(ms));
+        }
+        if let Some(ms) = database.max_lifetime_ms {
+            options =
+                options.max_lifetime(std::time::Duration::from_millis(ms));
+        }
+
+        let mut delay = database.retry_delay_ms;
+        for attempt in 1..=database.connect_retries {
+            match options.clone().connect(&database.url).await {
+                Ok(pool) => return Ok(pool),
+                Err(err) => warn!(
+                    "Connection attempt {attempt} failed: {err}. Retrying in \
+                    {delay} ms..."
+                ),
+            }
+
+            tokio::time::sleep(std::time::Duration::from_millis(delay)).await;
+            delay = delay.saturating_mul(2);
+        }
+
+        Ok(options.connect(&database.url).await?)
+    }
+
     /// Starts a new transaction. Returns an error if there is a previous
     /// transaction still live.
     /// # Errors
     /// Fails if there is already a live transaction
     pub async fn start_transaction(&mut self) -> Result<()> {
+        self.discard_implicit().await?;
         if self.current_transaction.is_some() {
             return Err(ArchivistError::TransactionAlreadyLive);
         }
@@ -108,6 +274,7 @@ impl Archivist {
     /// # Errors
     /// Fails if there is no live transaction. Forwards errors from `sqlx`.
     pub async fn commit_transaction(&mut self) -> Result<()> {
+        self.discard_implicit().await?;
         self.current_transaction
             .take()
             .ok_or(ArchivistError::NoTransactionToCommit)?
@@ -122,6 +289,7 @@ impl Archivist {
     /// # Errors
     /// Fails if there is no live transaction. Forwards errors from `sqlx`.
     pub async fn rollback_transaction(&mut self) -> Result<()> {
+        self.discard_implicit().await?;
         self.current_transaction
             .take()
             .ok_or(ArchivistError::NoTransactionToRollback)?
@@ -131,6 +299,49 @@ impl Archivist {
         Ok(())
     }
 
+    /// Sets a savepoint called `name` in the live transaction, to which it
+    /// can later be partly rolled back.
+    /// # Errors
+    /// Fails if there is no live transaction or `name` is not a plain
+    /// identifier. Forwards errors from `sqlx`.
+    pub async fn savepoint(&mut self, name: &str) -> Result<()> {
+        self.on_savepoint("savepoint", name).await
+    }
+
+    /// Releases the savepoint called `name`, keeping what was done since.
+    /// # Errors
+    /// Fails if there is no live transaction or `name` is not a plain
+    /// identifier. Forwards errors from `sqlx`.
+    pub async fn release_savepoint(&mut self, name: &str) -> Result<()> {
+        self.on_savepoint("release savepoint", name).await
+    }
+
+    /// Undos what was done in the live transaction since the savepoint
+    /// called `name`, leaving the rest of the transaction live.
+    /// # Errors
+    /// Fails if there is no live transaction or `name` is not a plain
+    /// identifier. Forwards errors from `sqlx`.
+    pub async fn rollback_to_savepoint(&mut self, name: &str) -> Result<()> {
+        self.on_savepoint("rollback to savepoint", name).await
+    }
+
+    async fn on_savepoint(&mut self, command: &str, name: &str) -> Result<()> {
+        self.discard_implicit().await?;
+        if !value::is_identifier(name) {
+            return Err(ArchivistError::InvalidSavepoint(name.to_string()));
+        }
+        let tx = self
+            .current_transaction
+            .as_mut()
+            .ok_or(ArchivistError::NoTransactionForSavepoint)?;
+
+        sqlx::query(&format!("{command} {name};"))
+            .execute(&mut **tx)
+            .await?;
+
+        Ok(())
+    }
+
     /// Checks whether a row with `id` exists in `table`.
     /// # Errors
     /// Forwards errors from `sqlx`.
@@ -162,20 +373,13 @@ impl Archivist {
     /// Fails if there is a collision. Forwards errors from `sqlx`.
     pub async fn assert_unique<T>(&self, item: &T) -> Result<()>
     where
-        T: TableItem,
+        T: InsertBinds,
     {
-        let uniques = item.unique_values();
-        if uniques.is_empty() {
-            return Ok(());
-        }
-
-        let query = format!("select id from {} where {};", T::TABLE, uniques,);
-        let id: Option<(i32,)> =
-            sqlx::query_as(&query).fetch_optional(&self.pool).await?;
+        let id = find_collision(&self.pool, item).await?;
 
-        id.map_or(Ok(()), |(id,)| {
+        id.map_or(Ok(()), |id| {
             Err(ArchivistError::EntryAlreadyExists(
-                item.insert_values(),
+                unique_key(item),
                 T::TABLE.to_string(),
                 id,
             ))
@@ -190,25 +394,104 @@ impl Archivist {
     /// Fails if there are collisions in the table. Forwards errors from `sqlx`.
     pub async fn insert<T>(&mut self, item: T) -> Result<i32>
     where
-        T: TableItem,
+        T: InsertBinds,
     {
         self.assert_unique(&item).await?;
 
-        // Enter the item
-        let query = format!(
-            "insert into {}({}) values ({}) returning id;",
-            T::TABLE,
-            T::insert_columns(),
-            item.insert_values(),
-        );
+        let tx = self.get_transaction().await?;
+        let result = insert_into(tx, &item).await;
+        self.finish_implicit(result).await
+    }
 
+    /// Adds a new entry to `T::TABLE`, unless one with the same unique
+    /// fields already exists. Returns the id of the new or existing item.
+    ///
+    /// This is safe against concurrent calls, as the check and the insertion
+    /// are done under a lock on `T::TABLE`, held until the transaction ends.
+    ///
+    /// # Errors
+    /// Forwards errors from `sqlx`.
+    pub async fn insert_or_get<T>(&mut self, item: T) -> Result<i32>
+    where
+        T: InsertBinds,
+    {
         let tx = self.get_transaction().await?;
-        let (id,) = sqlx::query_as(&query).fetch_one(&mut *tx).await?;
+        let result = async {
+            sqlx::query("select pg_advisory_xact_lock(hashtext($1));")
+                .bind(T::TABLE.name())
+                .execute(&mut *tx)
+                .await?;
 
-        Ok(id)
+            // Checked in the transaction, to see rows inserted in it
+            match find_collision(&mut *tx, &item).await? {
+                Some(id) => Ok(id),
+                None => insert_into(tx, &item).await,
+            }
+        }
+        .await;
+        self.finish_implicit(result).await
     }
 
-    /// Gets all items from `T::TABLE`.
+    /// Adds several new entries to `T::TABLE` in as few round trips as
+    /// possible, making sure no unique fields are duplicated, be it by rows
+    /// already in the table or by other items in `items`. Nothing is
+    /// inserted unless all are.
+    ///
+    /// Returns the ids of the newly inserted items, in the order given.
+    /// # Errors
+    /// Fails if there are collisions in the table or among `items`. Forwards
+    /// errors from `sqlx`.
+    pub async fn insert_many<T>(&mut self, items: Vec<T>) -> Result<Vec<i32>>
+    where
+        T: InsertBinds,
+    {
+        if items.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let columns = T::insert_columns().split(',').count();
+        let chunk_size = (MAX_PARAMETERS / columns).max(1);
+
+        let tx = self.get_transaction().await?;
+        let result = insert_chunks(tx, &items, chunk_size).await;
+        self.finish_implicit(result).await
+    }
+
+    /// Makes sure there is a user called `username`, inserting one with the
+    /// given details if not. Returns the user's id either way, so this can
+    /// safely be re-run, e.g. when provisioning.
+    ///
+    /// # Errors
+    /// Fails if the details are not valid for a new user. Forwards errors
+    /// from `sqlx`.
+    pub async fn ensure_user(
+        &mut self,
+        username: &str,
+        real_name: &str,
+        email: &str,
+        admin: bool,
+    ) -> std::result::Result<i32, ARPAError> {
+        let user = User::new(username, real_name, email, admin)?;
+
+        let existing: Option<(i32,)> = sqlx::query_as(&format!(
+            "select id from {} where username=$1;",
+            Table::Users
+        ))
+        .bind(user.username())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(ArchivistError::from)?;
+
+        if let Some((id,)) = existing {
+            debug!("User {} already exists with id {id}", user.username());
+            return Ok(id);
+        }
+
+        Ok(self.insert(user).await?)
+    }
+
+    /// Gets all items from `T::TABLE`. For large tables, prefer
+    /// [`Self::get_page`].
     /// # Errors
     /// Forwards errors from `sqlx`.
     pub async fn get_all<T>(&self) -> Result<Vec<T>>
@@ -217,11 +500,111 @@ impl Archivist {
     {
         let query = format!("select {} from {};", T::select(), T::TABLE,);
 
-        let items = sqlx::query_as(&query).fetch_all(&self.pool).await?;
+        let items: Vec<T> =
+            sqlx::query_as(&query).fetch_all(&self.pool).await?;
+        if items.len() > LARGE_TABLE {
+            warn!(
+                "Got all {} rows of {}; consider paging with get_page.",
+                items.len(),
+                T::TABLE
+            );
+        }
 
         Ok(items)
     }
 
+    /// Gets all items from `T::TABLE` as a JSON array, e.g. for exporting to
+    /// other tools. Checksums are written as hyphenated UUID strings, and
+    /// timestamps in RFC 3339.
+    /// # Errors
+    /// Forwards errors from `sqlx` and `serde_json`.
+    pub async fn get_all_json<T>(&self) -> Result<String>
+    where
+        T: TableItem + Serialize,
+    {
+        let items = self.get_all::<T>().await?;
+        Ok(serde_json::to_string(&items)?)
+    }
+
+    /// Streams all items from `T::TABLE`, so they never all have to be held
+    /// at once. Go through them with e.g. `StreamExt::next`.
+    pub fn stream_all<T>(&self) -> impl Stream<Item = Result<T>> + '_
+    where
+        T: TableItem + 'static,
+    {
+        sqlx::query_as(stream_query::<T>())
+            .fetch(&self.pool)
+            .map(|row| row.map_err(Into::into))
+    }
+
+    /// Gets at most `limit` items from `T::TABLE`, ordered by id, skipping
+    /// the first `offset`. An `offset` past the end gives an empty page.
+    /// # Errors
+    /// Forwards errors from `sqlx`.
+    pub async fn get_page<T>(&self, limit: i64, offset: i64) -> Result<Vec<T>>
+    where
+        T: TableItem,
+    {
+        let query = format!(
+            "select {} from {} order by id limit $1 offset $2;",
+            T::select(),
+            T::TABLE
+        );
+
+        let items = sqlx::query_as(&query)
+            .bind(limit)
+            .bind(offset)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(items)
+    }
+
+    /// Gets all items from `T::TABLE` that were inserted at or after `since`,
+    /// oldest first. This is meant for incrementally syncing to a downstream
+    /// system.
+    /// # Errors
+    /// Forwards errors from `sqlx`.
+    pub async fn export_since<T>(
+        &self,
+        since: time::OffsetDateTime,
+    ) -> Result<Vec<T>>
+    where
+        T: TableItem,
+    {
+        let query = format!(
+            "select {} from {} where created_at >= $1 order by created_at;",
+            T::select(),
+            T::TABLE,
+        );
+
+        let items = sqlx::query_as(&query)
+            .bind(since)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(items)
+    }
+
+    /// Counts the rows of `T::TABLE`, optionally only those fulfilling a
+    /// `where`-condition. An empty table gives 0.
+    ///
+    /// # Errors
+    /// Forwards errors from `sqlx`.
+    pub async fn count<T>(&self, condition: Option<&str>) -> Result<i64>
+    where
+        T: TableItem,
+    {
+        let query = condition.map_or_else(
+            || format!("select count(*) from {};", T::TABLE),
+            |c| format!("select count(*) from {} where {c};", T::TABLE),
+        );
+
+        let (count,) = sqlx::query_as(&query).fetch_one(&self.pool).await?;
+
+        Ok(count)
+    }
+
     /// Finds an item from `T::TABLE`, fulfilling a `where`-condition.
     ///
     /// This is essentially just wrapping a query like `select T from TABLE
@@ -245,11 +628,97 @@ impl Archivist {
         Ok(item)
     }
 
+    /// Same as [`Self::find`], but picks the first item when ordered by
+    /// `order_by`, e.g. the most recent with `created_at` descending.
+    ///
+    /// # Errors
+    /// Fails if `order_by` is not a plain column name. Forwards errors from
+    /// `sqlx`.
+    pub async fn find_ordered<T>(
+        &self,
+        condition: &str,
+        order_by: &str,
+        descending: bool,
+    ) -> Result<Option<T>>
+    where
+        T: TableItem,
+    {
+        if !value::is_identifier(order_by) {
+            return Err(ArchivistError::InvalidColumn(order_by.to_string()));
+        }
+
+        let query = format!(
+            "select {} from {} where {} order by {order_by} {} limit 1;",
+            T::select(),
+            T::TABLE,
+            condition,
+            if descending { "desc" } else { "asc" }
+        );
+
+        let item = sqlx::query_as(&query).fetch_optional(&self.pool).await?;
+
+        Ok(item)
+    }
+
+    /// Finds all items from `T::TABLE` fulfilling a `where`-condition.
+    ///
+    /// This is essentially just wrapping a query like `select T from TABLE
+    /// where CONDITION;`.
+    ///
+    /// # Errors
+    /// Forwards errors from `sqlx`.
+    pub async fn find_all<T>(&self, condition: &str) -> Result<Vec<T>>
+    where
+        T: TableItem,
+    {
+        let query = Self::find_all_query::<T>(condition);
+
+        let items = sqlx::query_as(&query).fetch_all(&self.pool).await?;
+
+        Ok(items)
+    }
+
+    /// Runs `explain (analyze, buffers)` on the exact query `find_all` would
+    /// run with `condition`, and returns the plan as text. Note that
+    /// `analyze` actually executes the query.
+    ///
+    /// # Errors
+    /// Forwards errors from `sqlx`.
+    pub async fn explain<T>(&self, condition: &str) -> Result<String>
+    where
+        T: TableItem,
+    {
+        let query = format!(
+            "explain (analyze, buffers) {}",
+            Self::find_all_query::<T>(condition)
+        );
+
+        let lines: Vec<(String,)> =
+            sqlx::query_as(&query).fetch_all(&self.pool).await?;
+
+        Ok(lines
+            .into_iter()
+            .map(|(line,)| line)
+            .collect::<Vec<_>>()
+            .join("\n"))
+    }
+
+    /// The select statement used by `find_all`.
+    fn find_all_query<T: TableItem>(condition: &str) -> String {
+        format!(
+            "select {} from {} where {};",
+            T::select(),
+            T::TABLE,
+            condition
+        )
+    }
+
     /// Update an entry with the given `id` in the given `table`. `value` in
     /// this case is a string like `number = 2`, i.e. both the column and the
     /// actual value.
     ///
-    /// Remember that string values need to be incased in single quotes.
+    /// Remember that string values need to be incased in single quotes. Prefer
+    /// [`Self::update_set`], which takes care of that for you.
     ///
     /// # Errors
     /// Forwards errors from `sqlx`.
@@ -264,9 +733,55 @@ impl Archivist {
         let query = format!("update {table} set {value} where id={id};");
 
         let tx = self.get_transaction().await?;
-        sqlx::query(&query).execute(tx).await?;
+        let result = sqlx::query(&query).execute(tx).await;
+        self.finish_implicit(result.map(|_| ()).map_err(Into::into))
+            .await
+    }
 
-        Ok(())
+    /// Update an entry with the given `id` in the given `table`, setting each
+    /// column in `assignments` to its value. Values are bound as parameters,
+    /// so no quoting is needed, and this is preferred over [`Self::update`].
+    ///
+    /// # Errors
+    /// Fails if `id` does not exist or a column is not a plain identifier.
+    /// Forwards errors from `sqlx`.
+    pub async fn update_set(
+        &mut self,
+        table: Table,
+        id: i32,
+        assignments: &[(&str, SqlValue)],
+    ) -> Result<()> {
+        if let Some((column, _)) =
+            assignments.iter().find(|(c, _)| !value::is_identifier(c))
+        {
+            return Err(ArchivistError::InvalidColumn((*column).to_string()));
+        }
+        self.assert_id(table, id).await?;
+
+        let mut n = 0;
+        let values = assignments
+            .iter()
+            .map(|(column, value)| {
+                if *value == SqlValue::Null {
+                    format!("{column}=null")
+                } else {
+                    n += 1;
+                    format!("{column}=${n}")
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        let query = format!("update {table} set {values} where id=${};", n + 1);
+
+        let mut query = sqlx::query(&query);
+        for (_, value) in assignments {
+            query = value.clone().bind(query);
+        }
+
+        let tx = self.get_transaction().await?;
+        let result = query.bind(id).execute(tx).await;
+        self.finish_implicit(result.map(|_| ()).map_err(Into::into))
+            .await
     }
 
     /// Updates all columns for a the row with the supplied `id`.
@@ -279,26 +794,31 @@ impl Archivist {
         id: i32,
     ) -> Result<()>
     where
-        T: TableItem,
+        T: InsertBinds,
     {
         self.assert_id(T::TABLE, id).await?;
 
-        let values = T::insert_columns()
-            .split(',')
-            .zip(item.insert_values().split(','))
-            .map(|(col, val)| format!("{col}={val}"))
-            .collect::<Vec<_>>()
-            .join(",");
+        // The values are assigned as a whole row, to match the columns
+        let values = row_binds(item)?;
+        let (placeholders, n) = value::placeholders(&values, 0);
+        let query = format!(
+            "update {} set ({}) = row({placeholders}) where id=${};",
+            T::TABLE,
+            T::insert_columns(),
+            n + 1
+        );
 
-        let query =
-            format!("update {} set {} where id={}", T::TABLE, values, id,);
+        debug!("Updating from cache: {query}");
 
-        info!("q {query}");
+        let mut query = sqlx::query(&query);
+        for value in values {
+            query = value.bind(query);
+        }
 
         let tx = self.get_transaction().await?;
-        sqlx::query(&query).execute(tx).await?;
-
-        Ok(())
+        let result = query.bind(id).execute(tx).await;
+        self.finish_implicit(result.map(|_| ()).map_err(Into::into))
+            .await
     }
 
     /// Gets an item whose id you know.
@@ -322,6 +842,145 @@ impl Archivist {
         Ok(item)
     }
 
+    /// Gets the items with the given `ids`, in one query, in the same order.
+    ///
+    /// # Errors
+    /// Fails if any of the ids don't exist, listing them. Forwards errors
+    /// from `sqlx`.
+    pub async fn get_many<T>(&self, ids: &[i32]) -> Result<Vec<T>>
+    where
+        T: TableItem,
+    {
+        let query = format!(
+            "select {} from {} \
+            join unnest($1::int[]) with ordinality as wanted(id, n) using (id) \
+            order by wanted.n;",
+            T::select(),
+            T::TABLE,
+        );
+        let items: Vec<T> = sqlx::query_as(&query)
+            .bind(ids)
+            .fetch_all(&self.pool)
+            .await?;
+
+        if items.len() != ids.len() {
+            let found = items.iter().map(T::id).collect::<HashSet<_>>();
+            let missing = ids
+                .iter()
+                .filter(|id| !found.contains(id))
+                .copied()
+                .collect();
+            return Err(ArchivistError::MissingIDs(T::TABLE, missing));
+        }
+
+        Ok(items)
+    }
+
+    /// Reads the contents of the file behind the item with `id`.
+    ///
+    /// # Errors
+    /// Fails if `id` does not exist or the file is missing or unreadable.
+    /// Forwards errors from `sqlx`.
+    pub async fn read_file<T>(
+        &self,
+        id: i32,
+    ) -> std::result::Result<Vec<u8>, ARPAError>
+    where
+        T: FileItem,
+    {
+        let item = self.get::<T>(id).await?;
+        assert_file_exists(item.file_path())?;
+
+        let bytes = tokio::fs::read(item.file_path()).await?;
+        Ok(bytes)
+    }
+
+    /// Opens the file behind the item with `id`, for when it is too large to
+    /// be read into memory at once.
+    ///
+    /// # Errors
+    /// Fails if `id` does not exist or the file is missing or unreadable.
+    /// Forwards errors from `sqlx`.
+    pub async fn open_file<T>(
+        &self,
+        id: i32,
+    ) -> std::result::Result<tokio::fs::File, ARPAError>
+    where
+        T: FileItem,
+    {
+        let item = self.get::<T>(id).await?;
+        assert_file_exists(item.file_path())?;
+
+        let file = tokio::fs::File::open(item.file_path()).await?;
+        Ok(file)
+    }
+
+    /// Sums up the on-disk sizes of the raw files of each pulsar, returning
+    /// pairs of pulsar id and bytes, ordered by id.
+    ///
+    /// Files that can't be found are skipped with a warning.
+    ///
+    /// # Errors
+    /// Forwards errors from `sqlx`.
+    pub async fn storage_usage(&self) -> Result<Vec<(i32, u64)>> {
+        let mut usage = BTreeMap::new();
+
+        for raw in self.get_all::<RawMeta>().await? {
+            match tokio::fs::metadata(&raw.file_path).await {
+                Ok(meta) => {
+                    *usage.entry(raw.pulsar_id).or_insert(0) += meta.len();
+                }
+                Err(err) => warn!(
+                    "Could not stat raw file {} (id = {}): {err}",
+                    raw.file_path, raw.id,
+                ),
+            }
+        }
+
+        Ok(usage.into_iter().collect())
+    }
+
+    /// Recomputes the checksums of all files in `T::TABLE`, with at most
+    /// `parallelism` files being read at once, and returns those that do not
+    /// match what is stored.
+    ///
+    /// # Errors
+    /// Fails if a worker can't be joined. Forwards errors from `sqlx`.
+    pub async fn verify_all_concurrent<T>(
+        &self,
+        parallelism: usize,
+    ) -> std::result::Result<Vec<Mismatch>, ARPAError>
+    where
+        T: ChecksumItem,
+    {
+        let items = self.get_all::<T>().await?;
+        info!("Verifying {} file(s) in {}...", items.len(), T::TABLE);
+        let algorithm = self.config.behaviour.checksum_algorithm;
+
+        let mut mismatches = Vec::new();
+        let mut tasks = JoinSet::new();
+        for item in items {
+            if tasks.len() >= parallelism.max(1)
+                && let Some(result) = tasks.join_next().await
+            {
+                mismatches.extend(result?);
+            }
+
+            let id = item.id();
+            let file_path = item.file_path().to_string();
+            let expected = item.checksum();
+            tasks.spawn_blocking(move || {
+                Mismatch::check(id, file_path, expected, algorithm)
+            });
+        }
+        while let Some(result) = tasks.join_next().await {
+            mismatches.extend(result?);
+        }
+
+        mismatches.sort_by_key(|m| m.id);
+        Ok(mismatches)
+    }
+
     /// Deletes an item from a table. Make sure you are providing the correct
     /// type, as there is no way of checking your intentions!
     ///
@@ -341,9 +1000,31 @@ impl Archivist {
         let query = format!("delete from {} where id={};", T::TABLE, id,);
 
         let tx = self.get_transaction().await?;
-        sqlx::query(&query).execute(tx).await?;
+        let result = sqlx::query(&query).execute(tx).await;
+        self.finish_implicit(result.map(|_| ()).map_err(Into::into))
+            .await
+    }
 
-        Ok(())
+    /// Deletes all items from `T::TABLE` fulfilling a `where`-condition, e.g.
+    /// all TOAs from a bad raw file. Returns how many were deleted.
+    ///
+    /// # Errors
+    /// Forwards errors from `sqlx`.
+    pub async fn delete_where<T>(&mut self, condition: &str) -> Result<u64>
+    where
+        T: TableItem,
+    {
+        let query = format!("delete from {} where {condition};", T::TABLE);
+
+        let tx = self.get_transaction().await?;
+        let result = sqlx::query(&query).execute(tx).await;
+        let deleted = self
+            .finish_implicit(result.map_err(Into::into))
+            .await?
+            .rows_affected();
+        info!("Deleted {deleted} row(s) from {}", T::TABLE);
+
+        Ok(deleted)
     }
 
     /// Gets the indicated values from `table`, for one row if it meets
@@ -373,27 +1054,423 @@ impl Archivist {
         Ok(item)
     }
 
+    /// Runs `analyze` on `table`, or on the whole DB if `None`, to update the
+    /// query planner's statistics.
+    ///
+    /// # Errors
+    /// Fails if there is a live transaction. Forwards errors from `sqlx`.
+    pub async fn analyze(&self, table: Option<Table>) -> Result<()> {
+        self.maintain("analyze", table).await
+    }
+
+    /// Runs `vacuum` on `table`, or on the whole DB if `None`, to reclaim
+    /// space after e.g. large deletions.
+    ///
+    /// # Errors
+    /// Fails if there is a live transaction. Forwards errors from `sqlx`.
+    pub async fn vacuum(&self, table: Option<Table>) -> Result<()> {
+        self.maintain("vacuum", table).await
+    }
+
+    /// Issues a maintenance `command`. These cannot run inside a
+    /// transaction, so they go straight to the pool, and we refuse to run
+    /// while our own transaction is live to not act on stale data.
+    async fn maintain(
+        &self,
+        command: &str,
+        table: Option<Table>,
+    ) -> Result<()> {
+        if self.current_transaction.is_some() && !self.implicit {
+            return Err(ArchivistError::MaintenanceInTransaction(
+                command.to_string(),
+            ));
+        }
+
+        let query = table.map_or_else(
+            || format!("{command};"),
+            |t| format!("{command} {t};"),
+        );
+        info!("Running \"{query}\"...");
+
+        sqlx::query(&query).execute(&self.pool).await?;
+
+        Ok(())
+    }
+
     /// Returns the currently live transaction. If there is none present, it
-    /// first creates one.
+    /// first creates an implicit one, which the caller must finish with
+    /// `finish_implicit` once its operation is done.
     async fn get_transaction(&mut self) -> Result<&mut PgConnection> {
+        self.discard_implicit().await?;
         if self.current_transaction.is_none() {
-            warn!("Started implicit transaction.");
+            debug!("Started implicit transaction.");
             self.current_transaction = Some(self.pool.begin().await?);
+            self.implicit = true;
         }
 
         Ok(self.current_transaction.as_mut().unwrap())
     }
 
+    /// Ends the current transaction if it was started implicitly, by
+    /// committing it if the operation's `result` is `Ok`, and rolling it back
+    /// otherwise, so a failed operation holds no locks. Explicit transactions
+    /// are left alone.
+    async fn finish_implicit<T>(&mut self, result: Result<T>) -> Result<T> {
+        if result.is_err() {
+            if let Err(err) = self.discard_implicit().await {
+                warn!("Could not roll back implicit transaction: {err}");
+            }
+            return result;
+        }
+        if !self.implicit {
+            return result;
+        }
+
+        self.implicit = false;
+        if let Some(tx) = self.current_transaction.take() {
+            tx.commit().await?;
+            debug!("Committed implicit transaction.");
+        }
+
+        result
+    }
+
+    /// Rolls back an implicit transaction left dangling by a failed
+    /// operation, e.g. one that was cancelled.
+    async fn discard_implicit(&mut self) -> Result<()> {
+        if !self.implicit {
+            return Ok(());
+        }
+
+        self.implicit = false;
+        if let Some(tx) = self.current_transaction.take() {
+            warn!("Rolling back implicit transaction of a failed operation.");
+            tx.rollback().await?;
+        }
+
+        Ok(())
+    }
+
     /// The current configuration.
-    pub const fn config(&self) -> &Config {
+    pub fn config(&self) -> &Config {
         &self.config
     }
+
+    /// The current configuration, for work that outlives a borrow, e.g. a
+    /// blocking task.
+    pub fn shared_config(&self) -> Arc<Config> {
+        Arc::clone(&self.config)
+    }
+
+    /// The diagnostics that can be run on cooked files.
+    pub const fn diagnostics(&self) -> &DiagnosticRegistry {
+        &self.diagnostics
+    }
+
+    /// The diagnostics that can be run on cooked files, e.g. to register
+    /// your own.
+    pub const fn diagnostics_mut(&mut self) -> &mut DiagnosticRegistry {
+        &mut self.diagnostics
+    }
+}
+
+/// The query behind [`Archivist::stream_all`] for `T`. It is made once per
+/// type and kept for good, as the stream borrows it for as long as it lives.
+fn stream_query<T: TableItem + 'static>() -> &'static str {
+    static QUERIES: LazyLock<Mutex<HashMap<TypeId, &'static str>>> =
+        LazyLock::new(Mutex::default);
+
+    let mut queries = QUERIES.lock().unwrap_or_else(PoisonError::into_inner);
+    queries.entry(TypeId::of::<T>()).or_insert_with(|| {
+        let query = format!("select {} from {};", T::select(), T::TABLE);
+        Box::leak(query.into_boxed_str())
+    })
+}
+
+/// Inserts `items` on `connection`, `chunk_size` at a time, making sure no
+/// unique fields are duplicated. Returns their ids, in order.
+async fn insert_chunks<T>(
+    connection: &mut PgConnection,
+    items: &[T],
+    chunk_size: usize,
+) -> Result<Vec<i32>>
+where
+    T: InsertBinds,
+{
+    let columns = T::insert_columns().split(',').count();
+    let mut ids = Vec::with_capacity(items.len());
+    for chunk in items.chunks(chunk_size) {
+        let mut n = 0;
+        let mut rows = Vec::with_capacity(chunk.len());
+        let mut values = Vec::with_capacity(chunk.len() * columns);
+        for item in chunk {
+            let row = row_binds(item)?;
+            let (placeholders, bound) = value::placeholders(&row, n);
+            n += bound;
+            rows.push(placeholders);
+            values.extend(row);
+        }
+
+        // Checked in the transaction, to see rows inserted in it,
+        // including those of earlier chunks
+        assert_unique_chunk(&mut *connection, chunk, &rows, &values).await?;
+
+        // Postgres returns the rows of a multi-row insert in order
+        let query = format!(
+            "insert into {}({}) values {} returning id;",
+            T::TABLE,
+            T::insert_columns(),
+            rows.iter()
+                .map(|row| format!("({row})"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        let mut query = sqlx::query(&query);
+        for value in values {
+            query = value.bind(query);
+        }
+
+        for row in query.fetch_all(&mut *connection).await? {
+            ids.push(row.try_get("id")?);
+        }
+    }
+
+    Ok(ids)
+}
+
+/// The id of a row in `T::TABLE` that `item` collides with, if any.
+async fn find_collision<'c, T>(
+    executor: impl PgExecutor<'c>,
+    item: &T,
+) -> Result<Option<i32>>
+where
+    T: InsertBinds,
+{
+    // Nulls never collide
+    let uniques = item
+        .unique_binds()
+        .into_iter()
+        .filter(|(_, value)| *value != SqlValue::Null)
+        .collect::<Vec<_>>();
+    if uniques.is_empty() {
+        return Ok(None);
+    }
+
+    let condition = uniques
+        .iter()
+        .enumerate()
+        .map(|(i, (column, _))| format!("{column}=${}", i + 1))
+        .collect::<Vec<_>>()
+        .join(" or ");
+    let query = format!("select id from {} where {condition};", T::TABLE);
+    let mut query = sqlx::query(&query);
+    for (_, value) in uniques {
+        query = value.bind(query);
+    }
+
+    match query.fetch_optional(executor).await? {
+        Some(row) => Ok(Some(row.try_get("id")?)),
+        None => Ok(None),
+    }
+}
+
+/// Returns an error if any of `chunk` collides with a row in `T::TABLE`, or
+/// with another item of `chunk`. The items' `rows` of placeholders for
+/// `values` are reused to check them as a table of their own.
+async fn assert_unique_chunk<T>(
+    connection: &mut PgConnection,
+    chunk: &[T],
+    rows: &[String],
+    values: &[SqlValue],
+) -> Result<()>
+where
+    T: InsertBinds,
+{
+    let Some(first) = chunk.first() else {
+        return Ok(());
+    };
+    let uniques = first.unique_binds();
+    if uniques.is_empty() {
+        return Ok(());
+    }
+
+    // Nulls never compare equal, so they never collide
+    let matching = |a: &str, b: &str| {
+        uniques
+            .iter()
+            .map(|(column, _)| format!("{a}.{column} = {b}.{column}"))
+            .collect::<Vec<_>>()
+            .join(" or ")
+    };
+    let batch = format!(
+        "with batch(arpa_row, {}) as (values {})",
+        T::insert_columns(),
+        rows.iter()
+            .enumerate()
+            .map(|(i, row)| format!("({i}, {row})"))
+            .collect::<Vec<_>>()
+            .join(", "),
+    );
+    let item = |row: &PgRow| -> Result<&T> {
+        let i: i32 = row.try_get("arpa_row")?;
+        Ok(&chunk[usize::try_from(i).unwrap_or_default()])
+    };
+
+    // Checked in the transaction, to see rows inserted in it, including
+    // those of earlier chunks
+    let existing = fetch_bound(
+        &mut *connection,
+        &format!(
+            "{batch} select b.arpa_row, t.id from batch b join {} t on {} \
+            order by b.arpa_row limit 1;",
+            T::TABLE,
+            matching("t", "b"),
+        ),
+        values,
+    )
+    .await?;
+    if let Some(row) = existing {
+        return Err(ArchivistError::EntryAlreadyExists(
+            unique_key(item(&row)?),
+            T::TABLE.to_string(),
+            row.try_get("id")?,
+        ));
+    }
+
+    let duplicate = fetch_bound(
+        connection,
+        &format!(
+            "{batch} select b.arpa_row from batch a join batch b \
+            on a.arpa_row < b.arpa_row and ({}) order by b.arpa_row limit 1;",
+            matching("a", "b"),
+        ),
+        values,
+    )
+    .await?;
+    if let Some(row) = duplicate {
+        return Err(ArchivistError::DuplicateInBatch(
+            unique_key(item(&row)?),
+            T::TABLE.to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Runs `query` with `values` bound, and gets its first row, if any.
+async fn fetch_bound(
+    connection: &mut PgConnection,
+    query: &str,
+    values: &[SqlValue],
+) -> Result<Option<PgRow>> {
+    let mut query = sqlx::query(query);
+    for value in values {
+        query = value.clone().bind(query);
+    }
+
+    Ok(query.fetch_optional(connection).await?)
+}
+
+/// Names the unique columns of `item`, to say what collided without putting
+/// its values in the error.
+fn unique_key<T: InsertBinds>(item: &T) -> String {
+    item.unique_binds()
+        .iter()
+        .map(|(column, _)| *column)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The values of `item` to bind for insertion, in the order of
+/// [`TableItem::insert_columns`].
+///
+/// # Errors
+/// Fails if the binds are not for exactly those columns, in that order, as
+/// the binds are written by hand while the columns are derived.
+fn row_binds<T: InsertBinds>(item: &T) -> Result<Vec<SqlValue>> {
+    let (names, values): (Vec<_>, Vec<_>) =
+        item.insert_binds().into_iter().unzip();
+    let columns = T::insert_columns()
+        .split(',')
+        .map(str::trim)
+        .collect::<Vec<_>>();
+    if names != columns {
+        return Err(ArchivistError::BindsMismatch(
+            T::TABLE,
+            columns.join(", "),
+            names.join(", "),
+        ));
+    }
+
+    Ok(values)
+}
+
+/// Inserts `item` on `connection`, with its values bound rather than quoted,
+/// and returns its id.
+async fn insert_into<T>(connection: &mut PgConnection, item: &T) -> Result<i32>
+where
+    T: InsertBinds,
+{
+    let values = row_binds(item)?;
+    let (placeholders, _) = value::placeholders(&values, 0);
+    let query = format!(
+        "insert into {}({}) values ({placeholders}) returning id;",
+        T::TABLE,
+        T::insert_columns()
+    );
+
+    let mut query = sqlx::query(&query);
+    for value in values {
+        query = value.bind(query);
+    }
+
+    Ok(query.fetch_one(connection).await?.try_get("id")?)
+}
+
+/// A file whose checksum does not match the one stored in the DB.
+#[derive(Debug, Clone)]
+pub struct Mismatch {
+    /// The id of the row referring to the file.
+    pub id: i32,
+    /// The path of the file.
+    pub file_path: String,
+    /// The checksum stored in the DB.
+    pub expected: Checksum,
+    /// The checksum of the file, or `None` if it couldn't be read.
+    pub found: Option<Checksum>,
+}
+impl Mismatch {
+    /// Computes the checksum of `file_path` with `algorithm`, returning a
+    /// mismatch if it differs from `expected`.
+    fn check(
+        id: i32,
+        file_path: String,
+        expected: Checksum,
+        algorithm: ChecksumAlgorithm,
+    ) -> Option<Self> {
+        let found =
+            match compute_checksum(&file_path, algorithm, &mut NoProgress) {
+                Ok(checksum) => Some(Checksum::from(checksum)),
+                Err(err) => {
+                    warn!("Could not read {file_path}: {err}");
+                    None
+                }
+            };
+
+        (found != Some(expected)).then_some(Self {
+            id,
+            file_path,
+            expected,
+            found,
+        })
+    }
 }
 
 impl Debug for Archivist {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Archivist")
             .field("live:", &self.current_transaction.is_some())
+            .field("implicit:", &self.implicit)
             .finish_non_exhaustive()
     }
 }
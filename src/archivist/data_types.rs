@@ -1,8 +1,10 @@
 //! Various datatypes, most of which represent `sql` tables.
 
+mod checksum;
 mod diagnostics;
 mod par_meta;
 mod process_meta;
+mod processed_meta;
 mod pulsar_meta;
 mod raw_meta;
 mod telescope;
@@ -10,11 +12,16 @@ mod template_meta;
 mod toa_info;
 mod user;
 
-pub use diagnostics::{DiagnosticFloat, DiagnosticPlot};
+pub use checksum::Checksum;
+pub use diagnostics::{DiagnosticFloat, DiagnosticPlot, DiagnosticValue};
 pub use par_meta::ParMeta;
 pub use process_meta::ProcessInfo;
+pub use processed_meta::ProcessedMeta;
 pub use pulsar_meta::PulsarMeta;
-pub use raw_meta::{RawFileHeader, RawMeta, archive_file};
+pub use raw_meta::{
+    Collision, HeaderItems, RawFileHeader, RawMeta, archive_copy, archive_file,
+    preview_archive_file,
+};
 pub use telescope::{ObsSystem, TelescopeId};
 pub use template_meta::TemplateMeta;
 pub use toa_info::TOAInfo;
@@ -0,0 +1,96 @@
+//! The checksum of a stored file.
+
+use serde::{Deserialize, Serialize};
+use sqlx::{
+    Decode, Encode, Postgres, Type,
+    encode::IsNull,
+    error::BoxDynError,
+    postgres::{PgArgumentBuffer, PgTypeInfo, PgValueRef},
+    types::uuid,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+/// A 128 bit checksum of a file, MD5 unless configured otherwise.
+///
+/// In the DB, these are stored as `UUID`s, but they are not UUIDs, so the
+/// conversion is kept to the DB boundary. They are shown, and serialised, as
+/// 32 hex digits.
+pub struct Checksum(u128);
+impl Checksum {
+    /// Wraps a checksum computed by e.g.
+    /// [`crate::conveniences::compute_checksum`].
+    pub const fn from_u128(value: u128) -> Self {
+        Self(value)
+    }
+
+    /// The checksum as a plain number.
+    pub const fn as_u128(self) -> u128 {
+        self.0
+    }
+}
+
+impl From<u128> for Checksum {
+    fn from(value: u128) -> Self {
+        Self(value)
+    }
+}
+impl From<uuid::Uuid> for Checksum {
+    fn from(value: uuid::Uuid) -> Self {
+        Self(value.as_u128())
+    }
+}
+impl From<Checksum> for uuid::Uuid {
+    fn from(value: Checksum) -> Self {
+        Self::from_u128(value.0)
+    }
+}
+
+impl std::fmt::Display for Checksum {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:032x}", self.0)
+    }
+}
+impl std::fmt::Debug for Checksum {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self}")
+    }
+}
+
+impl Type<Postgres> for Checksum {
+    fn type_info() -> PgTypeInfo {
+        <uuid::Uuid as Type<Postgres>>::type_info()
+    }
+}
+impl Encode<'_, Postgres> for Checksum {
+    fn encode_by_ref(
+        &self,
+        buf: &mut PgArgumentBuffer,
+    ) -> Result<IsNull, BoxDynError> {
+        <uuid::Uuid as Encode<Postgres>>::encode(uuid::Uuid::from(*self), buf)
+    }
+}
+impl<'r> Decode<'r, Postgres> for Checksum {
+    fn decode(value: PgValueRef<'r>) -> Result<Self, BoxDynError> {
+        Ok(<uuid::Uuid as Decode<Postgres>>::decode(value)?.into())
+    }
+}
+
+impl Serialize for Checksum {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+impl<'de> Deserialize<'de> for Checksum {
+    /// Takes 32 hex digits, with or without the dashes of a UUID.
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        u128::from_str_radix(&text.replace('-', ""), 16)
+            .map(Self)
+            .map_err(serde::de::Error::custom)
+    }
+}
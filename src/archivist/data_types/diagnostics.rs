@@ -1,9 +1,10 @@
 //! Diagnostic entries.
 
-use crate::TableItem;
+use crate::{FileItem, InsertBinds, SqlValue, TableItem};
 use item_macro::TableItem;
+use serde::{Deserialize, Serialize};
 
-#[derive(sqlx::FromRow, TableItem)]
+#[derive(sqlx::FromRow, TableItem, Serialize, Deserialize)]
 #[table(DiagnosticFloats)]
 /// An entry referring to a diagnostic wiht a float value.
 pub struct DiagnosticFloat {
@@ -17,7 +18,51 @@ pub struct DiagnosticFloat {
     /// The value of the result.
     pub result: f32,
 }
-#[derive(sqlx::FromRow, TableItem)]
+impl InsertBinds for DiagnosticFloat {
+    fn insert_binds(&self) -> Vec<(&'static str, SqlValue)> {
+        vec![
+            ("process", self.process.into()),
+            ("diagnostic", self.diagnostic.clone().into()),
+            ("result", self.result.into()),
+        ]
+    }
+
+    fn unique_binds(&self) -> Vec<(&'static str, SqlValue)> {
+        Vec::new()
+    }
+}
+#[derive(sqlx::FromRow, TableItem, Serialize, Deserialize)]
+#[table(DiagnosticValues)]
+/// An entry referring to one of several keyed float values of a diagnostic,
+/// e.g. the SNR of one channel.
+pub struct DiagnosticValue {
+    /// Mandatory id.
+    #[derived]
+    pub id: i32,
+    /// The process id that led to this.
+    pub process: i32,
+    /// The diagnostic name.
+    pub diagnostic: String,
+    /// What the value is of, e.g. `chan3`.
+    pub key: String,
+    /// The value of the result.
+    pub result: f32,
+}
+impl InsertBinds for DiagnosticValue {
+    fn insert_binds(&self) -> Vec<(&'static str, SqlValue)> {
+        vec![
+            ("process", self.process.into()),
+            ("diagnostic", self.diagnostic.clone().into()),
+            ("key", self.key.clone().into()),
+            ("result", self.result.into()),
+        ]
+    }
+
+    fn unique_binds(&self) -> Vec<(&'static str, SqlValue)> {
+        Vec::new()
+    }
+}
+#[derive(sqlx::FromRow, TableItem, Serialize, Deserialize)]
 #[table(DiagnosticPlots)]
 /// An entry referring to a diagnostic plot.
 pub struct DiagnosticPlot {
@@ -31,3 +76,21 @@ pub struct DiagnosticPlot {
     /// The path to the plot.
     pub filepath: String,
 }
+impl InsertBinds for DiagnosticPlot {
+    fn insert_binds(&self) -> Vec<(&'static str, SqlValue)> {
+        vec![
+            ("process", self.process.into()),
+            ("diagnostic", self.diagnostic.clone().into()),
+            ("filepath", self.filepath.clone().into()),
+        ]
+    }
+
+    fn unique_binds(&self) -> Vec<(&'static str, SqlValue)> {
+        Vec::new()
+    }
+}
+impl FileItem for DiagnosticPlot {
+    fn file_path(&self) -> &str {
+        &self.filepath
+    }
+}
@@ -1,10 +1,25 @@
 //! Metadata for ephemerides.
 
-use crate::{archivist::TableItem, conveniences::compute_checksum};
+use crate::{
+    Archivist, Result,
+    archivist::{
+        SqlValue,
+        table::{ChecksumItem, FileItem, InsertBinds, TableItem},
+    },
+    config::Config,
+    conveniences::compute_checksum,
+    data_types::{Checksum, PulsarMeta},
+};
 use item_macro::TableItem;
-use sqlx::types::uuid;
+use log::warn;
+use psrutils::{
+    error::PsruError,
+    parfile::{BinaryModel, FittedParameterValue, Parfile},
+};
+use serde::{Deserialize, Serialize};
+use std::{fs::File, io::BufReader};
 
-#[derive(Debug, Clone, sqlx::FromRow, TableItem)]
+#[derive(Debug, Clone, sqlx::FromRow, TableItem, Serialize, Deserialize)]
 #[table(ParMetas)]
 /// The metadata of an ephemeride
 pub struct ParMeta {
@@ -16,24 +31,133 @@ pub struct ParMeta {
     pub pulsar_id: i32,
     #[unique]
     /// The 128 bit checksum of the file.
-    pub checksum: uuid::Uuid,
+    pub checksum: Checksum,
     #[unique]
     /// The path to the actual file.
     pub file_path: String,
+
+    /// The pulsar name given in the file, if any.
+    pub psr_name: Option<String>,
+    /// The spin frequency, in Hz, if given.
+    pub f0: Option<f64>,
+    /// The spin frequency derivative, in Hz/s, if given.
+    pub f1: Option<f64>,
+    /// The dispersion measure, in pc/cm³, if given.
+    pub dm: Option<f64>,
+    /// The epoch of the period, in MJD, if given.
+    pub pepoch: Option<f64>,
+    /// The binary model, if any.
+    pub binary_model: Option<String>,
+}
+impl InsertBinds for ParMeta {
+    fn insert_binds(&self) -> Vec<(&'static str, SqlValue)> {
+        vec![
+            ("pulsar_id", self.pulsar_id.into()),
+            ("checksum", self.checksum.into()),
+            ("file_path", self.file_path.clone().into()),
+            ("psr_name", self.psr_name.clone().into()),
+            ("f0", self.f0.into()),
+            ("f1", self.f1.into()),
+            ("dm", self.dm.into()),
+            ("pepoch", self.pepoch.into()),
+            ("binary_model", self.binary_model.clone().into()),
+        ]
+    }
+
+    fn unique_binds(&self) -> Vec<(&'static str, SqlValue)> {
+        vec![
+            ("checksum", self.checksum.into()),
+            ("file_path", self.file_path.clone().into()),
+        ]
+    }
 }
 impl ParMeta {
-    /// Creates a new ephemeride meta object.
+    /// Creates a new ephemeride meta object, with the key parameters read
+    /// from the file. Any that can't be read are left out, and if the file
+    /// can't be parsed at all, all of them are.
     /// # Errors
     /// Will only pass on errors from the io calls made.
-    pub fn new(file_path: String, pulsar_id: i32) -> std::io::Result<Self> {
-        let u128 = compute_checksum(&file_path, true)?;
-        let checksum = uuid::Uuid::from_u128(u128);
+    pub fn new(
+        file_path: String,
+        pulsar_id: i32,
+        config: &Config,
+    ) -> std::io::Result<Self> {
+        let u128 = compute_checksum(
+            &file_path,
+            config.behaviour.checksum_algorithm,
+            config.behaviour.progress.reporter().as_mut(),
+        )?;
+        let checksum = Checksum::from(u128);
 
-        Ok(Self {
+        let mut meta = Self {
             id: 0,
             pulsar_id,
             checksum,
             file_path,
-        })
+            psr_name: None,
+            f0: None,
+            f1: None,
+            dm: None,
+            pepoch: None,
+            binary_model: None,
+        };
+        match read_parfile(&meta.file_path) {
+            Ok(parfile) => meta.set_parameters(&parfile),
+            Err(err) => warn!(
+                "Could not parse {}, so its parameters are left out: {err}",
+                meta.file_path
+            ),
+        }
+
+        Ok(meta)
+    }
+
+    fn set_parameters(&mut self, parfile: &Parfile) {
+        let parameter = |name| {
+            parfile
+                .parameters
+                .iter()
+                .find(|p| p.name() == name)
+                .and_then(|p| match p.value() {
+                    FittedParameterValue::Missing => None,
+                    FittedParameterValue::JustValue(value)
+                    | FittedParameterValue::FitInfo { value, .. } => {
+                        Some(*value)
+                    }
+                })
+        };
+
+        self.psr_name = parfile
+            .texts
+            .iter()
+            .find(|t| t.name() == "PSR")
+            .map(|t| t.value().clone());
+        self.f0 = parameter("F0");
+        self.f1 = parameter("F1");
+        self.dm = parameter("DM");
+        self.pepoch = parameter("PEPOCH");
+        self.binary_model = (parfile.binary_model != BinaryModel::Unstated)
+            .then(|| format!("{:?}", parfile.binary_model));
     }
+
+    /// Gets the pulsar this ephemeride is for.
+    /// # Errors
+    /// Fails if the pulsar is missing. Forwards errors from `sqlx`.
+    pub async fn pulsar(&self, archivist: &Archivist) -> Result<PulsarMeta> {
+        Ok(archivist.get(self.pulsar_id).await?)
+    }
+}
+impl FileItem for ParMeta {
+    fn file_path(&self) -> &str {
+        &self.file_path
+    }
+}
+impl ChecksumItem for ParMeta {
+    fn checksum(&self) -> Checksum {
+        self.checksum
+    }
+}
+
+fn read_parfile(path: &str) -> std::result::Result<Parfile, PsruError> {
+    Parfile::read(BufReader::new(File::open(path)?))
 }
@@ -1,10 +1,17 @@
 //! Process information.
 
-use crate::{archivist::table::TableItem, data_types::ParMeta};
+use crate::{
+    archivist::{
+        SqlValue,
+        table::{InsertBinds, TableItem},
+    },
+    data_types::ParMeta,
+};
 use item_macro::TableItem;
+use serde::{Deserialize, Serialize};
 use sqlx::prelude::FromRow;
 
-#[derive(FromRow, Clone, TableItem)]
+#[derive(FromRow, Clone, TableItem, Serialize, Deserialize)]
 #[table(ProcessMetas)]
 /// The information of a process
 pub struct ProcessInfo {
@@ -27,6 +34,23 @@ pub struct ProcessInfo {
     /// Which user launched the process.
     pub user_id: i32,
 }
+impl InsertBinds for ProcessInfo {
+    fn insert_binds(&self) -> Vec<(&'static str, SqlValue)> {
+        vec![
+            ("raw_id", self.raw_id.into()),
+            ("par_id", self.par_id.into()),
+            ("template_id", self.template_id.into()),
+            ("n_channels", self.n_channels.into()),
+            ("n_subints", self.n_subints.into()),
+            ("method", self.method.clone().into()),
+            ("user_id", self.user_id.into()),
+        ]
+    }
+
+    fn unique_binds(&self) -> Vec<(&'static str, SqlValue)> {
+        Vec::new()
+    }
+}
 impl ProcessInfo {
     pub(crate) fn new(
         user_id: i32,
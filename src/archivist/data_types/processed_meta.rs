@@ -0,0 +1,68 @@
+//! Metadata for processed files, i.e. archives made by the pipeline.
+
+use crate::{
+    archivist::{
+        SqlValue,
+        table::{ChecksumItem, FileItem, InsertBinds, TableItem},
+    },
+    data_types::Checksum,
+};
+use item_macro::TableItem;
+use serde::{Deserialize, Serialize};
+use sqlx::prelude::FromRow;
+
+#[derive(Debug, Clone, FromRow, TableItem, Serialize, Deserialize)]
+#[table(ProcessedMetas)]
+/// The metadata of a processed file, i.e. the scrunched archive, with any
+/// ephemeride installed, that TOAs were generated from.
+pub struct ProcessedMeta {
+    /// Mandatory id.
+    #[derived]
+    pub id: i32,
+    /// ID of the process that made it.
+    pub process_id: i32,
+    /// The 128 bit checksum of the file.
+    pub checksum: Checksum,
+    #[unique]
+    /// The path to the actual file.
+    pub file_path: String,
+}
+impl InsertBinds for ProcessedMeta {
+    fn insert_binds(&self) -> Vec<(&'static str, SqlValue)> {
+        vec![
+            ("process_id", self.process_id.into()),
+            ("checksum", self.checksum.into()),
+            ("file_path", self.file_path.clone().into()),
+        ]
+    }
+
+    fn unique_binds(&self) -> Vec<(&'static str, SqlValue)> {
+        vec![("file_path", self.file_path.clone().into())]
+    }
+}
+impl ProcessedMeta {
+    /// Creates a new processed file meta object, of a file that is already
+    /// archived.
+    pub const fn new(
+        process_id: i32,
+        checksum: u128,
+        file_path: String,
+    ) -> Self {
+        Self {
+            id: 0,
+            process_id,
+            checksum: Checksum::from_u128(checksum),
+            file_path,
+        }
+    }
+}
+impl FileItem for ProcessedMeta {
+    fn file_path(&self) -> &str {
+        &self.file_path
+    }
+}
+impl ChecksumItem for ProcessedMeta {
+    fn checksum(&self) -> Checksum {
+        self.checksum
+    }
+}
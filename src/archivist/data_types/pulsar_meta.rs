@@ -2,9 +2,13 @@
 
 use std::str::FromStr;
 
-use crate::{ARPAError, Table, archivist::TableItem};
+use crate::{
+    ARPAError, Table,
+    archivist::{InsertBinds, SqlValue, TableItem},
+};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, sqlx::FromRow, Clone)]
+#[derive(Debug, sqlx::FromRow, Clone, Serialize, Deserialize)]
 /// Metadata of a pulsar.
 pub struct PulsarMeta {
     /// Mandatory id.
@@ -188,6 +192,25 @@ impl TableItem for PulsarMeta {
         )
     }
 }
+impl InsertBinds for PulsarMeta {
+    fn insert_binds(&self) -> Vec<(&'static str, SqlValue)> {
+        vec![
+            ("alias", self.alias.clone().into()),
+            ("j_name", self.j_name.clone().into()),
+            ("b_name", self.b_name.clone().into()),
+            ("j2000_ra", self.j2000_ra.clone().into()),
+            ("j2000_dec", self.j2000_dec.clone().into()),
+            ("master_parfile_id", self.master_parfile_id.into()),
+        ]
+    }
+
+    fn unique_binds(&self) -> Vec<(&'static str, SqlValue)> {
+        vec![
+            ("alias", self.alias.clone().into()),
+            ("j_name", self.j_name.clone().into()),
+        ]
+    }
+}
 impl FromStr for PulsarMeta {
     type Err = ARPAError;
 
@@ -1,22 +1,29 @@
 //! Metadata of a stored rawfile.
 
 use crate::{
-    ARPAError, Archivist, Result,
-    archivist::table::TableItem,
+    ARPAError, Archivist, AutoAddEvent, Result,
+    archivist::{
+        SqlValue,
+        table::{ChecksumItem, FileItem, InsertBinds, TableItem},
+    },
     config::Config,
-    conveniences::{assert_exists, check_file_equality, compute_checksum},
-    data_types::{ObsSystem, PulsarMeta},
+    conveniences::{
+        NoProgress, assert_dir_exists, assert_file_exists, check_file_equality,
+        compute_checksum, compute_checksum_async, file_sizes_match,
+    },
+    data_types::{Checksum, ObsSystem, PulsarMeta},
 };
 use item_macro::TableItem;
 use log::{debug, info, warn};
-use sqlx::{prelude::FromRow, types::uuid};
+use sqlx::prelude::FromRow;
 use std::fs::File;
 use std::os::unix::fs::MetadataExt;
 
 mod header;
-pub use header::RawFileHeader;
+pub use header::{HeaderItems, RawFileHeader};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, FromRow, Clone, TableItem)]
+#[derive(Debug, FromRow, Clone, TableItem, Serialize, Deserialize)]
 #[table(RawMetas)]
 /// Metadata of a stored raw file.
 pub struct RawMeta {
@@ -29,13 +36,30 @@ pub struct RawMeta {
     pub file_path: String,
     /// 128 bit checksum.
     #[unique]
-    pub checksum: uuid::Uuid,
+    pub checksum: Checksum,
 
     /// ID of pulsar it refers to.
     pub pulsar_id: i32,
     /// ID of observation unit that produced file.
     pub observer_id: i32,
 }
+impl InsertBinds for RawMeta {
+    fn insert_binds(&self) -> Vec<(&'static str, SqlValue)> {
+        vec![
+            ("file_path", self.file_path.clone().into()),
+            ("checksum", self.checksum.into()),
+            ("pulsar_id", self.pulsar_id.into()),
+            ("observer_id", self.observer_id.into()),
+        ]
+    }
+
+    fn unique_binds(&self) -> Vec<(&'static str, SqlValue)> {
+        vec![
+            ("file_path", self.file_path.clone().into()),
+            ("checksum", self.checksum.into()),
+        ]
+    }
+}
 
 impl RawMeta {
     /// Prepares a raw file and returns its meta.
@@ -43,81 +67,129 @@ impl RawMeta {
     /// Fails if
     ///  - the specified path does not exist;
     ///  - the header can't be read;
+    ///  - the object type is not allowed, with `strict_header`;
     ///  - the observation system is missing;
     ///  - the `archivist` encounters an error.
     pub async fn parse(archivist: &mut Archivist, path: &str) -> Result<Self> {
-        assert_exists(path)?;
+        Self::parse_inner(archivist, path, false, None).await
+    }
+
+    /// Like [`RawMeta::parse`], but with the checksum of the file already
+    /// computed, e.g. by [`crate::conveniences::compute_checksum_async`], so
+    /// the file is not hashed again. An archived copy is still checked
+    /// against it.
+    ///
+    /// # Errors
+    /// As [`RawMeta::parse`].
+    pub async fn parse_with_checksum(
+        archivist: &mut Archivist,
+        path: &str,
+        checksum: u128,
+    ) -> Result<Self> {
+        Self::parse_inner(archivist, path, false, Some(checksum)).await
+    }
+
+    /// Like [`RawMeta::parse`], but without writing anything: the file is not
+    /// archived, and an unknown pulsar is not added. This is meant for dry
+    /// runs of the pipeline.
+    ///
+    /// # Errors
+    /// As [`RawMeta::parse`], and also if the pulsar is unknown.
+    pub async fn parse_dry_run(
+        archivist: &mut Archivist,
+        path: &str,
+    ) -> Result<Self> {
+        Self::parse_inner(archivist, path, true, None).await
+    }
+
+    async fn parse_inner(
+        archivist: &mut Archivist,
+        path: &str,
+        dry_run: bool,
+        known_checksum: Option<u128>,
+    ) -> Result<Self> {
+        assert_file_exists(path)?;
+
+        if let Some(max) = archivist.config().behaviour.max_rawfile_bytes {
+            let size = File::open(path)?.metadata()?.size();
+            if size > max {
+                return Err(ARPAError::FileTooLarge(
+                    path.to_string(),
+                    size,
+                    max,
+                ));
+            }
+        }
 
         // Check that the file is ok
-        let header = RawFileHeader::get(archivist.config(), path)?;
+        let header = RawFileHeader::get_cached(archivist.config(), path)?;
         debug!("Got raw header info.");
+        check_object_type(archivist.config(), path, &header)?;
 
         // TODO also get user id and put it into meta
 
         // Get telescope name
         let obs_system = ObsSystem::find(
             archivist,
-            &header.telescope.to_lowercase(),
-            &header.receiver.to_lowercase(),
-            &header.backend.to_lowercase(),
+            &header.telescope,
+            &header.receiver,
+            &header.backend,
         )
         .await?
-        .ok_or(ARPAError::CantFind(format!(
-            "Obssystem in registry... \n\
-            (Telescope: {}, frontend: {}, backend: {}).",
-            &header.telescope, &header.receiver, &header.backend,
-        )))?;
+        .ok_or_else(|| {
+            let (telescope, receiver, backend) = ObsSystem::normalise(
+                &header.telescope,
+                &header.receiver,
+                &header.backend,
+            );
+            ARPAError::CantFind(format!(
+                "Obssystem in registry... \n\
+                (Telescope: {}, frontend: {}, backend: {}; \
+                searched for '{telescope}', '{receiver}', '{backend}').",
+                &header.telescope, &header.receiver, &header.backend,
+            ))
+        })?;
         let observer_id = obs_system.id;
         debug!("Found observation system.");
 
-        // Get pulsar name
-        let res = archivist
-            .find::<PulsarMeta>(&format!("j_name='{}'", &header.psr_name,))
-            .await?;
-
-        let pulsar_id = if let Some(r) = res {
-            r.id()
-        } else {
-            debug!("Unrecognised pulsar.");
-
-            if !archivist.config().behaviour.auto_add_pulsars {
-                return Err(ARPAError::CantFind(format!(
-                    "Pulsar with name '{}', and we're not set to auto-add.",
-                    &header.psr_name,
-                )));
-            }
-
-            info!("Adding pulsar '{}'", &header.psr_name);
-            let mut meta = PulsarMeta {
-                id: 0,
-                alias: header.psr_name.to_string(),
-                j_name: None,
-                b_name: None,
-                j2000_ra: None,
-                j2000_dec: None,
-                master_parfile_id: None,
-            };
-            meta.verify()?;
-            archivist.insert(meta).await?
-        };
+        let pulsar_id = find_pulsar(archivist, &header, path, dry_run).await?;
 
         // Move the file into a better spot in the archive
+        let config = archivist.config();
         let mut file_path = path.to_string();
-        let checksum = if archivist.config().behaviour.archive_rawfiles {
+        let checksum = if !dry_run && config.behaviour.archive_rawfiles {
             info!("Archiving file...");
-            let directory = header.get_intended_directory(archivist.config());
-            archive_file(
-                archivist.config(),
+            assert_dir_exists(&config.paths.rawfile_storage)?;
+            let directory = header.get_intended_directory(config);
+            place_file(
+                config,
                 &mut file_path,
                 &directory,
                 &header.filename,
-            )?
+                config.behaviour.move_rawfiles,
+                known_checksum,
+            )
+            .await?
         } else {
-            info!("Currently set to not archive raw files...");
-            compute_checksum(&file_path, true)?
+            if dry_run && config.behaviour.archive_rawfiles {
+                info!(
+                    "Dry run: would archive file in {}.",
+                    header.get_intended_directory(config)
+                );
+            } else if !dry_run {
+                info!("Currently set to not archive raw files...");
+            }
+            match known_checksum {
+                Some(checksum) => checksum,
+                None => compute_checksum(
+                    &file_path,
+                    config.behaviour.checksum_algorithm,
+                    config.behaviour.progress.reporter().as_mut(),
+                )?,
+            }
         };
 
-        let checksum = uuid::Uuid::from_u128(checksum);
+        let checksum = Checksum::from(checksum);
 
         Ok(Self {
             id: 0,
@@ -129,72 +201,192 @@ impl RawMeta {
     }
 }
 
+impl FileItem for RawMeta {
+    fn file_path(&self) -> &str {
+        &self.file_path
+    }
+}
+impl ChecksumItem for RawMeta {
+    fn checksum(&self) -> Checksum {
+        self.checksum
+    }
+}
+
+/// Gets the id of the pulsar named in `header`, adding it if allowed and not
+/// a dry run.
+async fn find_pulsar(
+    archivist: &mut Archivist,
+    header: &RawFileHeader,
+    path: &str,
+    dry_run: bool,
+) -> Result<i32> {
+    let res = archivist.find_pulsar_by_any_name(&header.psr_name).await?;
+
+    if let Some(r) = res {
+        return Ok(r.id());
+    }
+    debug!("Unrecognised pulsar.");
+
+    if !archivist.config().behaviour.auto_add_pulsars {
+        return Err(ARPAError::CantFind(format!(
+            "Pulsar with name '{}', and we're not set to auto-add.",
+            &header.psr_name,
+        )));
+    }
+
+    if dry_run {
+        return Err(ARPAError::CantFind(format!(
+            "Pulsar with name '{}', which would be added, but this is \
+            a dry run.",
+            &header.psr_name,
+        )));
+    }
+
+    info!("Adding pulsar '{}'", &header.psr_name);
+    let mut meta = PulsarMeta {
+        id: 0,
+        alias: header.psr_name.to_string(),
+        j_name: None,
+        b_name: None,
+        j2000_ra: None,
+        j2000_dec: None,
+        master_parfile_id: None,
+    };
+    meta.verify()?;
+    let id = archivist.insert(meta).await?;
+    archivist
+        .auto_added(AutoAddEvent::Pulsar {
+            id,
+            alias: header.psr_name.clone(),
+            raw_file: path.to_string(),
+        })
+        .await?;
+
+    Ok(id)
+}
+
+/// With `strict_header`, fails if the object type of the raw file at `path`
+/// is not allowed.
+fn check_object_type(
+    config: &Config,
+    path: &str,
+    header: &RawFileHeader,
+) -> Result<()> {
+    let behaviour = &config.behaviour;
+    if behaviour.strict_header
+        && !behaviour.allowed_object_types.contains(&header.object_type)
+    {
+        return Err(ARPAError::UnexpectedObjectType(
+            path.to_string(),
+            header.object_type.clone(),
+        ));
+    }
+
+    Ok(())
+}
+
 /// Puts the file in a good spot. To speed up copying and checksum calculations
-/// some thigns are done concurrently.
+/// some thigns are done concurrently, without blocking the async runtime.
 ///
 /// # Errors
-/// There are only two cases:
-///  1) the io calls fail; and
-///  2) the threads can't be joined.
-pub fn archive_file(
+/// Fails if the io calls fail, or the checksums of the copy and the source
+/// don't match.
+pub async fn archive_file(
     config: &Config,
     source: &mut String,
     directory: &str,
     name: &str,
+) -> Result<u128> {
+    place_file(
+        config,
+        source,
+        directory,
+        name,
+        config.behaviour.move_rawfiles,
+        None,
+    )
+    .await
+}
+
+/// Same as [`archive_file`], but always copies the file, leaving the source
+/// in place regardless of `behaviour.move_rawfiles`.
+///
+/// # Errors
+/// Same as [`archive_file`].
+pub async fn archive_copy(
+    config: &Config,
+    source: &mut String,
+    directory: &str,
+    name: &str,
+) -> Result<u128> {
+    place_file(config, source, directory, name, false, None).await
+}
+
+/// Copies the file at `source` to `directory`/`name`, removing the source
+/// afterwards if `move_source`. The copy is checked against `known_checksum`
+/// if given, instead of hashing the source again.
+async fn place_file(
+    config: &Config,
+    source: &mut String,
+    directory: &str,
+    name: &str,
+    move_source: bool,
+    known_checksum: Option<u128>,
 ) -> Result<u128> {
     let path = format!("{directory}/{name}");
 
     if source == &path {
         warn!("File is already where it should be ({source}).");
-        return Ok(0);
+        return Ok(known_checksum.unwrap_or(0));
     }
 
-    std::fs::create_dir_all(directory)?;
-    if std::fs::exists(&path)? {
-        return check_file_equality(source, path);
+    let algorithm = config.behaviour.checksum_algorithm;
+    tokio::fs::create_dir_all(directory).await?;
+    if tokio::fs::try_exists(&path).await? {
+        return check_file_equality(
+            source,
+            path,
+            algorithm,
+            config.behaviour.progress.reporter(),
+        )
+        .await;
     }
 
     // Both of these tasks can take some time, so they might as well run
     // concurrently. Even though they access the same file, they are both only
-    // reading it. Should be ok.
-    let sc = source.clone();
-    let dc = path.clone();
-    let copy_handle = std::thread::spawn(|| std::fs::copy(sc, dc));
-    let sc = source.clone();
-    let src_checksum_handle =
-        std::thread::spawn(move || compute_checksum(sc, true));
-
-    // If it turns out the copy is faster than the src checksum, we can start
-    // the dst checksum early. If not, we haven't lost anyhting here.
-    let dst_size = copy_handle
-        .join()
-        .map_err(|err| ARPAError::JoinThread(format!("{err:?}")))??;
-
-    let dc = path.clone();
-    let dst_checksum_handle =
-        std::thread::spawn(move || compute_checksum(dc, false));
-
-    let src_size = File::open(&source)?.metadata()?.size();
-
-    let src_checksum = src_checksum_handle
-        .join()
-        .map_err(|err| ARPAError::JoinThread(format!("{err:?}")))??;
-    let dst_checksum = dst_checksum_handle
-        .join()
-        .map_err(|err| ARPAError::JoinThread(format!("{err:?}")))??;
+    // reading it. Should be ok. If it turns out the copy is faster than the
+    // src checksum, we can start the dst checksum early.
+    let copy_and_checksum = async {
+        let size = tokio::fs::copy(&*source, &path).await?;
+        let checksum =
+            compute_checksum_async(&path, algorithm, Box::new(NoProgress))
+                .await?;
+        Ok::<_, std::io::Error>((size, checksum))
+    };
+    let src_checksum = async {
+        match known_checksum {
+            Some(checksum) => Ok(checksum),
+            None => {
+                compute_checksum_async(
+                    &*source,
+                    algorithm,
+                    config.behaviour.progress.reporter(),
+                )
+                .await
+            }
+        }
+    };
+    let ((dst_size, dst_checksum), src_checksum) =
+        tokio::try_join!(copy_and_checksum, src_checksum)?;
+
+    let src_size = tokio::fs::metadata(&*source).await?.len();
 
     if src_checksum != dst_checksum || src_size != dst_size {
-        // return Err(ARPAError::FileCopy(
-        //     src_checksum,
-        //     dst_checksum,
-        //     src_size,
-        //     dst_size,
-        // ));
         return Err(ARPAError::ChecksumFail(path));
     }
 
-    if config.behaviour.move_rawfiles {
-        std::fs::remove_file(&source)?;
+    if move_source {
+        tokio::fs::remove_file(&*source).await?;
         info!("Successfully moved {source} to {path}");
     } else {
         info!("Successfully copied {source} to {path}");
@@ -204,3 +396,44 @@ pub fn archive_file(
 
     Ok(src_checksum)
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// What [`archive_file`] would run into at its destination.
+pub enum Collision {
+    /// Nothing is there, so the file would be put there.
+    Absent,
+    /// The source already is at the destination.
+    InPlace,
+    /// A file of the same size is there, which is likely the same file.
+    PresentEqual,
+    /// A different file is there.
+    PresentDifferent,
+}
+
+/// Previews what [`archive_file`] would do, without copying or reading any
+/// files. Returns the destination path and what is found there.
+///
+/// As only the file sizes are compared, [`Collision::PresentEqual`] is not
+/// verified by checksum.
+///
+/// # Errors
+/// Fails if the files can't be stat'ed.
+pub fn preview_archive_file(
+    source: &str,
+    directory: &str,
+    name: &str,
+) -> Result<(String, Collision)> {
+    let path = format!("{directory}/{name}");
+
+    let collision = if source == path {
+        Collision::InPlace
+    } else if !std::fs::exists(&path)? {
+        Collision::Absent
+    } else if file_sizes_match(source, &path)? {
+        Collision::PresentEqual
+    } else {
+        Collision::PresentDifferent
+    };
+
+    Ok((path, collision))
+}
@@ -1,8 +1,26 @@
 use crate::{
-    ARPAError, Result, config::Config, conveniences::parse,
+    ARPAError, Result,
+    config::{Config, DiagnosticsLayout},
+    conveniences::parse,
     external_tools::psrchive,
 };
+use log::{debug, warn};
 use psrutils::data_types::{J2000Dec, J2000Ra, Mjd};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, str::FromStr, time::UNIX_EPOCH};
+
+/// The `vap` keys making up a [`RawFileHeader`].
+const HEADER_KEYS: [&str; 22] = [
+    "nbin", "nchan", "npol", "nsub", "type", "telescop", "name", "dec", "ra",
+    "freq", "bw", "dm", "rm", "scale", "state", "length", "rcvr", "basis",
+    "backend", "mjd", "tbin", "period",
+];
+
+/// What `psrchive::vap` prints for a header item the file has no value for.
+const MISSING_VALUE: &str = "*";
+
+/// Appended to the path of a raw file to get its header cache.
+const SIDECAR_SUFFIX: &str = ".arpa-header.json";
 
 #[derive(Debug)]
 #[allow(missing_docs)]
@@ -33,6 +51,17 @@ pub struct RawFileHeader {
     pub basis: String,
     pub backend: String,
     pub date: Mjd,
+
+    /// The sampling time, in seconds, if given.
+    pub sample_time: Option<f64>,
+    /// The folding period, in seconds, if given.
+    pub period: Option<f64>,
+    /// The centre frequency of each channel, in the same unit as
+    /// `frequency`. These are computed from `frequency`, `bw`, and
+    /// `channel_count` as if the channels evenly split the band, and are not
+    /// read from the file, so they may differ from what it has per channel.
+    /// With a negative bandwidth, they are in descending order.
+    pub computed_channel_frequencies: Vec<f32>,
 }
 impl RawFileHeader {
     /// Calls `psrchive::vap` to get the header of a raw file.
@@ -40,110 +69,130 @@ impl RawFileHeader {
     /// This depends on a call to `psrchive` that may fail for various reasons,
     /// but there are also many `parse` calls that fail.
     pub fn get(config: &Config, file_path: &str) -> Result<Self> {
-        let index = file_path.rfind('/').map_or(0, |i| i + 1);
-        let filename = file_path[index..].to_string();
+        let items = Self::get_items(config, file_path, &HEADER_KEYS)?;
+        Self::from_items(file_path, &items)
+    }
+
+    /// Same as [`Self::get`], but with `behaviour.cache_headers` the values
+    /// from `psrchive::vap` are kept in a `.arpa-header.json` sidecar next to
+    /// the file, and read from there as long as the file's size and
+    /// modification time are unchanged.
+    ///
+    /// Failing to write the sidecar, e.g. in read-only storage, is only
+    /// warned about.
+    ///
+    /// # Errors
+    /// Same as [`Self::get`].
+    pub fn get_cached(config: &Config, file_path: &str) -> Result<Self> {
+        if !config.behaviour.cache_headers {
+            return Self::get(config, file_path);
+        }
 
-        let keys = [
-            "nbin", "nchan", "npol", "nsub", "type", "telescop", "name", "dec",
-            "ra", "freq", "bw", "dm", "rm", "scale", "state", "length", "rcvr",
-            "basis", "backend", "mjd",
-        ];
+        let stamp = FileStamp::of(file_path)?;
+        let sidecar_path = format!("{file_path}{SIDECAR_SUFFIX}");
+        if let Some(items) = Sidecar::read(&sidecar_path, &stamp) {
+            debug!("Using cached header of {file_path}.");
+            return Self::from_items(file_path, &items);
+        }
 
-        let values = Self::get_items(config, file_path, &keys)?;
+        let items = Self::get_items(config, file_path, &HEADER_KEYS)?;
+        let sidecar = Sidecar {
+            stamp,
+            items: items.values.clone(),
+        };
+        if let Err(err) = sidecar.write(&sidecar_path) {
+            warn!("Could not cache the header of {file_path}: {err}");
+        }
+
+        Self::from_items(file_path, &items)
+    }
+
+    /// Parses the header of the file at `file_path` from its `vap` values.
+    fn from_items(file_path: &str, items: &HeaderItems) -> Result<Self> {
+        let index = file_path.rfind('/').map_or(0, |i| i + 1);
+        let filename = file_path[index..].to_string();
 
-        let mut i = 0;
         let header = Self {
             filename,
-            bin_count: parse({
-                i += 1;
-                &values[i]
-            })?,
-            channel_count: parse({
-                i += 1;
-                &values[i]
-            })?,
-            polarization_count: parse({
-                i += 1;
-                &values[i]
-            })?,
-            sub_count: parse({
-                i += 1;
-                &values[i]
-            })?,
-            object_type: parse({
-                i += 1;
-                &values[i]
-            })?,
-            telescope: parse({
-                i += 1;
-                &values[i]
-            })?,
-            psr_name: parse({
-                i += 1;
-                &values[i]
-            })?,
-            dec: parse({
-                i += 1;
-                &values[i]
-            })?,
-            ra: parse({
-                i += 1;
-                &values[i]
-            })?,
-            frequency: parse({
-                i += 1;
-                &values[i]
-            })?,
-            bw: parse({
-                i += 1;
-                &values[i]
-            })?,
-            dm: parse({
-                i += 1;
-                &values[i]
-            })?,
-            rm: parse({
-                i += 1;
-                &values[i]
-            })?,
-            scale: parse({
-                i += 1;
-                &values[i]
-            })?,
-            state: parse({
-                i += 1;
-                &values[i]
-            })?,
-            length: parse({
-                i += 1;
-                &values[i]
-            })?,
-            receiver: parse({
-                i += 1;
-                &values[i]
-            })?,
-            basis: parse({
-                i += 1;
-                &values[i]
-            })?,
-            backend: parse({
-                i += 1;
-                &values[i]
-            })?,
-            date: parse({
-                i += 1;
-                &values[i]
-            })?,
+            bin_count: items.parse("nbin")?,
+            channel_count: items.parse("nchan")?,
+            polarization_count: items.parse("npol")?,
+            sub_count: items.parse("nsub")?,
+            object_type: items.parse("type")?,
+            telescope: items.parse("telescop")?,
+            psr_name: items.parse("name")?,
+            dec: items.parse("dec")?,
+            ra: items.parse("ra")?,
+            frequency: items.parse("freq")?,
+            bw: items.parse("bw")?,
+            dm: items.parse("dm")?,
+            rm: items.parse("rm")?,
+            scale: items.parse("scale")?,
+            state: items.parse("state")?,
+            length: items.parse("length")?,
+            receiver: items.parse("rcvr")?,
+            basis: items.parse("basis")?,
+            backend: items.parse("backend")?,
+            date: items.parse("mjd")?,
+            sample_time: items.parse_optional("tbin")?,
+            period: items.parse_optional("period")?,
+            computed_channel_frequencies: Vec::new(),
         };
 
-        Ok(header)
+        Ok(Self {
+            computed_channel_frequencies: header.centre_frequencies(),
+            ..header
+        })
+    }
+
+    /// The centre frequency of each channel, assuming they evenly split the
+    /// band.
+    fn centre_frequencies(&self) -> Vec<f32> {
+        #[allow(clippy::cast_precision_loss)]
+        let width = self.bw / self.channel_count as f32;
+        let low = self.frequency - self.bw / 2.0;
+
+        (0..self.channel_count)
+            .map(|i| {
+                #[allow(clippy::cast_precision_loss)]
+                let i = i as f32;
+                (i + 0.5).mul_add(width, low)
+            })
+            .collect()
+    }
+
+    /// The integration time of each subint, in seconds, assuming they are
+    /// all the same length.
+    pub fn subint_length(&self) -> f32 {
+        #[allow(clippy::cast_precision_loss)]
+        let count = self.sub_count.max(1) as f32;
+        self.length / count
     }
 
     /// Forms a directory structure suitable for this file.
     pub fn get_intended_directory(&self, config: &Config) -> String {
+        format!("{}/{}", config.paths.rawfile_storage, self.hierarchy())
+    }
+
+    /// Forms the directory in which diagnostics cross-references to this
+    /// file should be put, according to the configured layout.
+    pub fn get_crossref_directory(&self, config: &Config) -> String {
+        let root = &config.paths.diagnostics_dir;
+        match config.behaviour.diagnostics_layout {
+            DiagnosticsLayout::Flat => root.clone(),
+            DiagnosticsLayout::Pulsar => {
+                format!("{root}/{}", self.psr_name.to_uppercase())
+            }
+            DiagnosticsLayout::Hierarchy => {
+                format!("{root}/{}", self.hierarchy())
+            }
+        }
+    }
+
+    /// The pulsar/telescope/receiver/backend part of the storage paths.
+    fn hierarchy(&self) -> String {
         format!(
-            "{}/{}/{}/{}/{}",
-            config.paths.rawfile_storage,
+            "{}/{}/{}/{}",
             self.psr_name.to_uppercase(),
             self.telescope.to_lowercase(),
             self.receiver.to_lowercase(),
@@ -151,29 +200,172 @@ impl RawFileHeader {
         )
     }
 
-    /// Calls `psrchive::vap` to get header items.
+    /// Calls `psrchive::vap` to get header items, keyed by the names in
+    /// `keys`.
+    ///
+    /// `vap` starts its output with the file name, which may contain spaces,
+    /// so the values are taken from the end.
+    /// ```
+    /// # use argos_arpa::{config::Config, data_types::RawFileHeader};
+    /// # use std::os::unix::fs::PermissionsExt;
+    /// # let dir = std::env::temp_dir().join("arpa vap doctest");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    /// # let config_path = dir.join("config.toml");
+    /// # std::fs::write(&config_path, format!(r#"
+    /// # [database]
+    /// # url = ""
+    /// # pool_connections = 1
+    /// # connection_timeout = 1
+    /// # [behaviour]
+    /// # archive_rawfiles = false
+    /// # move_rawfiles = false
+    /// # auto_add_pulsars = false
+    /// # auto_resolve_duplicate_uploads = false
+    /// # toa_fitting = "PGS"
+    /// # diagnostics = []
+    /// # [paths]
+    /// # psrchive = "{}"
+    /// # rawfile_storage = ""
+    /// # temp_dir = "/tmp"
+    /// # diagnostics_dir = ""
+    /// # "#, dir.display())).unwrap();
+    /// # let config = Config::load(&config_path).unwrap();
+    /// // A stand-in for `vap`, printing the file name and two values
+    /// let vap = dir.join("vap");
+    /// let script = "#!/bin/sh\nfor f; do :; done\necho \"$f 1024 64\"\n";
+    /// std::fs::write(&vap, script).unwrap();
+    /// std::fs::set_permissions(&vap, std::fs::Permissions::from_mode(0o755))
+    ///     .unwrap();
+    ///
+    /// let path = "/data/an observation with spaces.ar";
+    /// let keys = ["nbin", "nchan"];
+    /// let items = RawFileHeader::get_items(&config, path, &keys).unwrap();
+    /// assert_eq!(items.get("nbin"), Some("1024"));
+    /// assert_eq!(items.get("nchan"), Some("64"));
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
     ///
     /// # Errors
-    /// Fails only if `psrchive` can't be called.
+    /// Fails if `psrchive` can't be called, or returns the wrong number of
+    /// values.
     pub fn get_items(
         config: &Config,
         path: &str,
         keys: &[&str],
-    ) -> Result<Vec<String>> {
+    ) -> Result<HeaderItems> {
         let column_string = keys.join(",");
         let result =
             psrchive(config, "vap", &["-n", "-c", &column_string, path])?;
 
-        // We get a string of values
-        let values = result
-            .split_whitespace()
-            .map(str::to_string)
-            .collect::<Vec<_>>();
+        // We get a string of values, the first one being the filename. It
+        // may contain spaces, so the values are counted from the end.
+        let values = result.split_whitespace().collect::<Vec<_>>();
 
-        if values.len() != keys.len() + 1 {
+        if values.len() <= keys.len() {
             return Err(ARPAError::VapKeyCount(keys.len() + 1, values.len()));
         }
 
-        Ok(values)
+        let values = keys
+            .iter()
+            .zip(&values[values.len() - keys.len()..])
+            .map(|(key, value)| ((*key).to_string(), (*value).to_string()))
+            .collect();
+
+        Ok(HeaderItems { values })
+    }
+}
+
+/// Header items from `psrchive::vap`, by name.
+#[derive(Debug, Clone)]
+pub struct HeaderItems {
+    values: HashMap<String, String>,
+}
+impl HeaderItems {
+    /// The raw value of `key`, if it was asked for.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.values.get(key).map(String::as_str)
+    }
+
+    /// Parses the value of `key`.
+    ///
+    /// # Errors
+    /// Fails if `key` was not asked for, or its value can't be parsed.
+    pub fn parse<T>(&self, key: &str) -> Result<T>
+    where
+        T: FromStr + std::fmt::Debug,
+    {
+        let value = self
+            .get(key)
+            .ok_or_else(|| ARPAError::CantFind(format!("header item {key}")))?;
+
+        parse(value)
+    }
+
+    /// Parses the value of `key`, which `vap` gives as `*` if the file has
+    /// none.
+    ///
+    /// # Errors
+    /// As [`Self::parse`], for values other than the missing marker.
+    pub fn parse_optional<T>(&self, key: &str) -> Result<Option<T>>
+    where
+        T: FromStr + std::fmt::Debug,
+    {
+        if self.get(key) == Some(MISSING_VALUE) {
+            return Ok(None);
+        }
+
+        self.parse(key).map(Some)
+    }
+}
+
+/// What a header cache is checked against, to tell if the file changed.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct FileStamp {
+    size: u64,
+    modified_secs: u64,
+    modified_nanos: u32,
+}
+impl FileStamp {
+    fn of(path: &str) -> Result<Self> {
+        let metadata = std::fs::metadata(path)?;
+        let modified = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+
+        Ok(Self {
+            size: metadata.len(),
+            modified_secs: modified.as_secs(),
+            modified_nanos: modified.subsec_nanos(),
+        })
+    }
+}
+
+/// The contents of a `.arpa-header.json` sidecar.
+#[derive(Serialize, Deserialize)]
+struct Sidecar {
+    stamp: FileStamp,
+    items: HashMap<String, String>,
+}
+impl Sidecar {
+    /// The cached items, if the sidecar exists, matches `stamp`, and has all
+    /// the header keys.
+    fn read(path: &str, stamp: &FileStamp) -> Option<HeaderItems> {
+        let data = std::fs::read_to_string(path).ok()?;
+        let sidecar = serde_json::from_str::<Self>(&data).ok()?;
+
+        let complete = HEADER_KEYS
+            .iter()
+            .all(|key| sidecar.items.contains_key(*key));
+        (sidecar.stamp == *stamp && complete).then_some(HeaderItems {
+            values: sidecar.items,
+        })
+    }
+
+    fn write(&self, path: &str) -> Result<()> {
+        let data = serde_json::to_string(self).map_err(std::io::Error::from)?;
+        std::fs::write(path, data)?;
+
+        Ok(())
     }
 }
@@ -1,9 +1,11 @@
 //! Telescope and observation information.
 
-use crate::{ARPAError, Archivist, Result, TableItem};
+use crate::{ARPAError, Archivist, InsertBinds, Result, SqlValue, TableItem};
 use item_macro::TableItem;
+use log::debug;
+use serde::{Deserialize, Serialize};
 
-#[derive(sqlx::FromRow, TableItem)]
+#[derive(sqlx::FromRow, TableItem, Serialize, Deserialize)]
 #[table(Telescopes)]
 /// Identifier of a telescope.
 pub struct TelescopeId {
@@ -16,8 +18,24 @@ pub struct TelescopeId {
     #[unique]
     code: String,
 }
+impl InsertBinds for TelescopeId {
+    fn insert_binds(&self) -> Vec<(&'static str, SqlValue)> {
+        vec![
+            ("name", self.name.clone().into()),
+            ("abbreviation", self.abbreviation.clone().into()),
+            ("code", self.code.clone().into()),
+        ]
+    }
 
-#[derive(sqlx::FromRow, TableItem)]
+    fn unique_binds(&self) -> Vec<(&'static str, SqlValue)> {
+        vec![
+            ("name", self.name.clone().into()),
+            ("code", self.code.clone().into()),
+        ]
+    }
+}
+
+#[derive(sqlx::FromRow, TableItem, Serialize, Deserialize)]
 #[table(ObsSystems)]
 /// An observation system.
 pub struct ObsSystem {
@@ -33,6 +51,22 @@ pub struct ObsSystem {
     clock: String,
     code: String,
 }
+impl InsertBinds for ObsSystem {
+    fn insert_binds(&self) -> Vec<(&'static str, SqlValue)> {
+        vec![
+            ("name", self.name.clone().into()),
+            ("telescope_id", self.telescope_id.into()),
+            ("frontend", self.frontend.clone().into()),
+            ("backend", self.backend.clone().into()),
+            ("clock", self.clock.clone().into()),
+            ("code", self.code.clone().into()),
+        ]
+    }
+
+    fn unique_binds(&self) -> Vec<(&'static str, SqlValue)> {
+        vec![("name", self.name.clone().into())]
+    }
+}
 impl ObsSystem {
     /// Tries to find an `ObsSystem` from the DB.
     /// # Errors
@@ -43,11 +77,16 @@ impl ObsSystem {
         receiver: &str,
         backend: &str,
     ) -> Result<Option<Self>> {
-        // Normalise name
+        let (name, receiver, backend) =
+            Self::normalise(name, receiver, backend);
+        debug!(
+            "Looking for obs system with telescope '{name}', frontend \
+            '{receiver}', and backend '{backend}'."
+        );
+
         let telescope = archivist
             .find::<TelescopeId>(&format!(
-                "name='{0}' or abbreviation='{0}'",
-                name.to_lowercase(),
+                "name='{name}' or abbreviation='{name}'",
             ))
             .await?
             .ok_or(ARPAError::CantFind(format!(
@@ -56,13 +95,36 @@ impl ObsSystem {
 
         let finding = archivist
             .find(&format!(
-                "telescope_id={} and frontend='{}' and backend='{}'",
+                "telescope_id={} and frontend='{receiver}' and \
+                backend='{backend}'",
                 telescope.id,
-                receiver.to_lowercase(),
-                backend.to_ascii_lowercase(),
             ))
             .await?;
 
         Ok(finding)
     }
+
+    /// The name of this system, e.g. `eff_rfsoc_p217`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The site code used in `.tim` files, e.g. `g`.
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    /// The forms of telescope name, receiver, and backend that are actually
+    /// used when searching the DB.
+    pub fn normalise(
+        name: &str,
+        receiver: &str,
+        backend: &str,
+    ) -> (String, String, String) {
+        (
+            name.trim().to_lowercase(),
+            receiver.trim().to_lowercase(),
+            backend.trim().to_lowercase(),
+        )
+    }
 }
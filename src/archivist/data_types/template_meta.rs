@@ -1,11 +1,16 @@
 //! Metadata for a template file.
 
-use crate::archivist::table::TableItem;
+use crate::archivist::SqlValue;
+use crate::archivist::table::{ChecksumItem, FileItem, InsertBinds, TableItem};
+use crate::config::Config;
 use crate::conveniences::compute_checksum;
+use crate::data_types::{Checksum, PulsarMeta};
+use crate::{Archivist, Result};
 use item_macro::TableItem;
-use sqlx::{prelude::FromRow, types::uuid};
+use serde::{Deserialize, Serialize};
+use sqlx::prelude::FromRow;
 
-#[derive(Debug, FromRow, Clone, TableItem)]
+#[derive(Debug, FromRow, Clone, TableItem, Serialize, Deserialize)]
 #[table(TemplateMetas)]
 /// Metadata for a template file.
 pub struct TemplateMeta {
@@ -22,16 +27,40 @@ pub struct TemplateMeta {
 
     /// 128 bit checksum.
     #[unique]
-    pub checksum: uuid::Uuid,
+    pub checksum: Checksum,
+}
+impl InsertBinds for TemplateMeta {
+    fn insert_binds(&self) -> Vec<(&'static str, SqlValue)> {
+        vec![
+            ("pulsar_id", self.pulsar_id.into()),
+            ("file_path", self.file_path.clone().into()),
+            ("checksum", self.checksum.into()),
+        ]
+    }
+
+    fn unique_binds(&self) -> Vec<(&'static str, SqlValue)> {
+        vec![
+            ("file_path", self.file_path.clone().into()),
+            ("checksum", self.checksum.into()),
+        ]
+    }
 }
 impl TemplateMeta {
     /// Creates a new template metafile.
     ///
     /// # Errors
     /// Fails if the file can't be read.
-    pub fn new(file_path: String, pulsar_id: i32) -> std::io::Result<Self> {
-        let u128 = compute_checksum(&file_path, true)?;
-        let checksum = uuid::Uuid::from_u128(u128);
+    pub fn new(
+        file_path: String,
+        pulsar_id: i32,
+        config: &Config,
+    ) -> std::io::Result<Self> {
+        let u128 = compute_checksum(
+            &file_path,
+            config.behaviour.checksum_algorithm,
+            config.behaviour.progress.reporter().as_mut(),
+        )?;
+        let checksum = Checksum::from(u128);
 
         Ok(Self {
             id: 0,
@@ -40,4 +69,21 @@ impl TemplateMeta {
             checksum,
         })
     }
+
+    /// Gets the pulsar this template is for.
+    /// # Errors
+    /// Fails if the pulsar is missing. Forwards errors from `sqlx`.
+    pub async fn pulsar(&self, archivist: &Archivist) -> Result<PulsarMeta> {
+        Ok(archivist.get(self.pulsar_id).await?)
+    }
+}
+impl FileItem for TemplateMeta {
+    fn file_path(&self) -> &str {
+        &self.file_path
+    }
+}
+impl ChecksumItem for TemplateMeta {
+    fn checksum(&self) -> Checksum {
+        self.checksum
+    }
 }
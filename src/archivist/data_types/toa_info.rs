@@ -1,9 +1,12 @@
 //! Data for generated TOAs.
 
-use crate::archivist::table::TableItem;
+use crate::archivist::SqlValue;
+use crate::archivist::table::{InsertBinds, TableItem};
 use item_macro::TableItem;
+use psrutils::timfile::Flag;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, sqlx::FromRow, TableItem)]
+#[derive(Debug, sqlx::FromRow, TableItem, Serialize, Deserialize)]
 #[table(Toas)]
 /// TOA information. This comes from `psrchive`.
 pub struct TOAInfo {
@@ -31,6 +34,31 @@ pub struct TOAInfo {
     pub toa_err: f32,
     /// The frequency of this observation.
     pub frequency: f32,
+    /// The index of the subintegration this came from, if sub-banded.
+    pub subint: Option<i32>,
+    /// The index of the channel this came from, if sub-banded.
+    pub chan: Option<i32>,
+}
+impl InsertBinds for TOAInfo {
+    fn insert_binds(&self) -> Vec<(&'static str, SqlValue)> {
+        vec![
+            ("process_id", self.process_id.into()),
+            ("template_id", self.template_id.into()),
+            ("rawfile_id", self.rawfile_id.into()),
+            ("pulsar_id", self.pulsar_id.into()),
+            ("observer_id", self.observer_id.into()),
+            ("toa_int", self.toa_int.into()),
+            ("toa_frac", self.toa_frac.into()),
+            ("toa_err", self.toa_err.into()),
+            ("frequency", self.frequency.into()),
+            ("subint", self.subint.into()),
+            ("chan", self.chan.into()),
+        ]
+    }
+
+    fn unique_binds(&self) -> Vec<(&'static str, SqlValue)> {
+        Vec::new()
+    }
 }
 
 impl TOAInfo {
@@ -59,6 +87,40 @@ impl TOAInfo {
             toa_frac,
             toa_err: toa.mjd_error as f32,
             frequency: toa.frequency as f32,
+            subint: index_flag(toa, "subint"),
+            chan: index_flag(toa, "chan"),
         }
     }
+
+    /// Makes a tempo2 `.tim` line of this TOA, the inverse of
+    /// [`Self::extract`]. The sub-band indices are written back as the
+    /// `-subint` and `-chan` flags.
+    pub fn to_tim_line(&self, file: &str, site: &str) -> String {
+        // `f64` never displays with an exponent, so this is "0" or "0.xxx"
+        let frac = self.toa_frac.to_string();
+        let digits = frac.strip_prefix("0.").unwrap_or("0");
+
+        let subint = self
+            .subint
+            .map(|subint| format!(" -subint {subint}"))
+            .unwrap_or_default();
+        let chan = self
+            .chan
+            .map(|chan| format!(" -chan {chan}"))
+            .unwrap_or_default();
+
+        format!(
+            "{file} {} {}.{digits} {} {site}{subint}{chan}",
+            self.frequency, self.toa_int, self.toa_err,
+        )
+    }
+}
+
+/// Gets an index from `psrchive::pat`'s flags, if present.
+#[allow(clippy::cast_possible_truncation)]
+fn index_flag(toa: &psrutils::timfile::TOAInfo, flag: &str) -> Option<i32> {
+    match toa.flags.get(flag) {
+        Some(Flag::Double(index)) => Some(*index as i32),
+        _ => None,
+    }
 }
@@ -3,9 +3,16 @@
 use item_macro::TableItem;
 use sqlx::types::time;
 
-use crate::{ARPAError, Result, archivist::table::TableItem};
+use crate::{
+    ARPAError, Result,
+    archivist::{
+        SqlValue,
+        table::{InsertBinds, TableItem},
+    },
+};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, sqlx::FromRow, TableItem)]
+#[derive(Debug, sqlx::FromRow, TableItem, Serialize, Deserialize)]
 #[table(Users)]
 /// A user on this machine.
 pub struct User {
@@ -19,8 +26,27 @@ pub struct User {
     email: String,
     is_admin: bool,
 
+    #[serde(with = "::time::serde::rfc3339")]
     created_at: time::OffsetDateTime,
 }
+impl InsertBinds for User {
+    fn insert_binds(&self) -> Vec<(&'static str, SqlValue)> {
+        vec![
+            ("username", self.username.clone().into()),
+            ("real_name", self.real_name.clone().into()),
+            ("email", self.email.clone().into()),
+            ("is_admin", self.is_admin.into()),
+            ("created_at", self.created_at.into()),
+        ]
+    }
+
+    fn unique_binds(&self) -> Vec<(&'static str, SqlValue)> {
+        vec![
+            ("username", self.username.clone().into()),
+            ("email", self.email.clone().into()),
+        ]
+    }
+}
 
 impl User {
     /// Creates a new user object.
@@ -90,6 +116,11 @@ impl User {
         Ok(email.to_string())
     }
 
+    /// The (lowercase) username.
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
     /// The time this user was created (in the host's timekeeping system).
     pub const fn created_at(&self) -> time::OffsetDateTime {
         self.created_at
@@ -3,25 +3,39 @@ use super::Table;
 #[derive(Debug)]
 pub enum ArchivistError {
     Sqlx(sqlx::Error),
+    Json(serde_json::Error),
 
     EntryAlreadyExists(String, String, i32),
+    DuplicateInBatch(String, String),
 
     NoTransactionToCommit,
     NoTransactionToRollback,
     TransactionAlreadyLive,
+    NoTransactionForSavepoint,
+    InvalidSavepoint(String),
+    MaintenanceInTransaction(String),
 
     MissingID(Table, i32),
+    MissingIDs(Table, Vec<i32>),
+    InvalidColumn(String),
+    BindsMismatch(Table, String, String),
+    DuplicateMigration(String),
 }
 
 impl std::fmt::Display for ArchivistError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Sqlx(error) => write!(f, "[sqlx] {error}",),
+            Self::Json(error) => write!(f, "[serde_json] {error}"),
 
             Self::EntryAlreadyExists(key, table, id) => write!(
                 f,
                 "({key}) conflicts with preexisting entry (id = {id}) in {table}",
             ),
+            Self::DuplicateInBatch(key, table) => write!(
+                f,
+                "({key}) conflicts with another entry being inserted in {table}",
+            ),
 
             Self::NoTransactionToCommit => write!(
                 f,
@@ -38,11 +52,51 @@ impl std::fmt::Display for ArchivistError {
                 "Archivist was asked to start a transaction, but one is \
                 already live."
             ),
+            Self::NoTransactionForSavepoint => write!(
+                f,
+                "Archivist was asked to use a savepoint, but no transaction \
+                is live."
+            ),
+            Self::InvalidSavepoint(name) => {
+                write!(f, "\"{name}\" is not a valid savepoint name.")
+            }
+            Self::MaintenanceInTransaction(command) => write!(
+                f,
+                "Archivist was asked to {command}, but that cannot be done \
+                while a transaction is live."
+            ),
 
             Self::MissingID(table, id) => write!(
                 f,
                 "There is no entry with id {id} in table \"{table}\".",
             ),
+            Self::MissingIDs(table, ids) => write!(
+                f,
+                "There are no entries with ids {ids:?} in table \"{table}\"."
+            ),
+            Self::InvalidColumn(column) => {
+                write!(f, "\"{column}\" is not a valid column name.")
+            }
+            Self::BindsMismatch(table, columns, binds) => write!(
+                f,
+                "The values inserted into \"{table}\" are bound for \
+                ({binds}), but its columns are ({columns})."
+            ),
+            Self::DuplicateMigration(name) => write!(
+                f,
+                "Several setup files are called \"{name}\"; migrations are \
+                tracked by name, so they must be unique."
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ArchivistError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Sqlx(error) => Some(error),
+            Self::Json(error) => Some(error),
+            _ => None,
         }
     }
 }
@@ -52,3 +106,8 @@ impl From<sqlx::Error> for ArchivistError {
         Self::Sqlx(value)
     }
 }
+impl From<serde_json::Error> for ArchivistError {
+    fn from(value: serde_json::Error) -> Self {
+        Self::Json(value)
+    }
+}
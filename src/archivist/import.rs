@@ -0,0 +1,182 @@
+//! Importing an existing archive of raw files.
+
+use std::path::{Path, PathBuf};
+
+use log::{info, warn};
+use tokio::task::JoinSet;
+
+use super::Archivist;
+use crate::{
+    ARPAError,
+    conveniences::{NoProgress, compute_checksum_async},
+    data_types::RawMeta,
+};
+
+/// The extensions of the files picked up by [`Archivist::import_tree`].
+const ARCHIVE_EXTENSIONS: [&str; 4] = ["ar", "rf", "cf", "fits"];
+
+/// What happened to a file found by [`Archivist::import_tree`].
+#[derive(Debug, Clone)]
+pub enum ImportOutcome {
+    /// It was imported as the raw file with this id.
+    Imported(i32),
+    /// It was already imported, as the raw file with this id.
+    Skipped(i32),
+    /// It could not be imported, for this reason.
+    Failed(String),
+}
+
+/// What [`Archivist::import_tree`] did with each file it found.
+#[derive(Debug, Clone, Default)]
+pub struct ImportReport {
+    /// Each file, with what happened to it, in the order they were found.
+    pub outcomes: Vec<(String, ImportOutcome)>,
+}
+impl ImportReport {
+    /// How many files were imported.
+    pub fn imported(&self) -> usize {
+        self.count(|o| matches!(o, ImportOutcome::Imported(_)))
+    }
+
+    /// How many files were skipped, as they were already imported.
+    pub fn skipped(&self) -> usize {
+        self.count(|o| matches!(o, ImportOutcome::Skipped(_)))
+    }
+
+    /// How many files could not be imported.
+    pub fn failed(&self) -> usize {
+        self.count(|o| matches!(o, ImportOutcome::Failed(_)))
+    }
+
+    fn count(&self, f: impl Fn(&ImportOutcome) -> bool) -> usize {
+        self.outcomes.iter().filter(|(_, o)| f(o)).count()
+    }
+}
+
+impl Archivist {
+    /// Imports every archive file (`.ar`, `.rf`, `.cf`, or `.fits`) under
+    /// `root`, as if each was given to
+    /// [`crate::pipeline::parse_input_raw`]. Pulsars and observation systems
+    /// are resolved as configured, e.g. with `auto_add_pulsars`.
+    ///
+    /// A file that can't be imported does not stop the rest; it is reported
+    /// as failed instead. Files whose checksum is already in the archive are
+    /// skipped, so an interrupted import can safely be re-run.
+    ///
+    /// Up to `parallelism` files are checksummed at once, on blocking
+    /// threads, while the rest of the import, which goes through the DB,
+    /// handles one file at a time as their checksums are done. The
+    /// `callback` is called with each file and its outcome in that order,
+    /// while the report lists them in the order they were found.
+    ///
+    /// # Errors
+    /// Fails if `root` can't be walked, or a checksum task can't be joined.
+    pub async fn import_tree<F: Fn(&str, &ImportOutcome)>(
+        &mut self,
+        root: impl AsRef<Path>,
+        parallelism: usize,
+        callback: F,
+    ) -> Result<ImportReport, ARPAError> {
+        let mut files = Vec::new();
+        find_archives(root.as_ref(), &mut files)?;
+        info!(
+            "Importing {} file(s) from {}...",
+            files.len(),
+            root.as_ref().display()
+        );
+
+        let algorithm = self.config().behaviour.checksum_algorithm;
+        let mut files = files.into_iter().enumerate();
+        let mut tasks = JoinSet::new();
+        let mut outcomes = Vec::new();
+        loop {
+            while tasks.len() < parallelism.max(1)
+                && let Some((index, file)) = files.next()
+            {
+                let path = file.to_string_lossy().to_string();
+                tasks.spawn(async move {
+                    let checksum = compute_checksum_async(
+                        &path,
+                        algorithm,
+                        Box::new(NoProgress),
+                    )
+                    .await;
+                    (index, path, checksum)
+                });
+            }
+
+            let Some(joined) = tasks.join_next().await else {
+                break;
+            };
+            let (index, path, checksum) = joined?;
+            let outcome = match self.import_file(&path, checksum).await {
+                Ok(outcome) => outcome,
+                Err(err) => {
+                    warn!("Could not import {path}: {err}");
+                    ImportOutcome::Failed(err.to_string())
+                }
+            };
+
+            callback(&path, &outcome);
+            outcomes.push((index, path, outcome));
+        }
+
+        outcomes.sort_by_key(|(index, ..)| *index);
+        let report = ImportReport {
+            outcomes: outcomes
+                .into_iter()
+                .map(|(_, path, outcome)| (path, outcome))
+                .collect(),
+        };
+
+        info!(
+            "Imported {}, skipped {}, and failed {} file(s).",
+            report.imported(),
+            report.skipped(),
+            report.failed()
+        );
+        Ok(report)
+    }
+
+    /// Imports the raw file at `path`, with its already computed `checksum`,
+    /// unless it already is.
+    async fn import_file(
+        &mut self,
+        path: &str,
+        checksum: std::io::Result<u128>,
+    ) -> Result<ImportOutcome, ARPAError> {
+        let checksum = checksum?;
+        let existing = self
+            .find::<RawMeta>(&format!("checksum='{checksum:032x}'"))
+            .await?;
+        if let Some(raw) = existing {
+            return Ok(ImportOutcome::Skipped(raw.id));
+        }
+
+        let raw = RawMeta::parse_with_checksum(self, path, checksum).await?;
+        let id = self.insert(raw).await?;
+
+        Ok(ImportOutcome::Imported(id))
+    }
+}
+
+/// Collects the archive files under `dir`, in order of their paths.
+fn find_archives(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    let mut entries = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort();
+
+    for path in entries {
+        if path.is_dir() {
+            find_archives(&path, files)?;
+        } else if path
+            .extension()
+            .is_some_and(|e| ARCHIVE_EXTENSIONS.iter().any(|a| e == *a))
+        {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
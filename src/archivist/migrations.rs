@@ -0,0 +1,88 @@
+//! Applying SQL setup files as numbered migrations, each exactly once.
+//!
+//! Applied files are recorded by name in `schema_migrations`, along with a
+//! checksum of their contents. A file that changes after being applied is not
+//! run again, so schema changes go in new files.
+
+use std::collections::HashSet;
+
+use log::{debug, info, warn};
+use md5::{Digest, Md5};
+use sqlx::{Pool, Postgres};
+
+use super::{ArchivistError, Result};
+
+/// Keeps several archivists starting at once from applying the same file.
+const MIGRATION_LOCK: i64 = 0x6172_7061;
+
+/// Applies the `migrations`, given as file name and contents, in order,
+/// skipping those already applied.
+pub(super) async fn run_migrations<'a>(
+    pool: &Pool<Postgres>,
+    migrations: impl Iterator<Item = (&'a str, &'a str)>,
+) -> Result<()> {
+    sqlx::query(
+        "create table if not exists schema_migrations (\
+            name text primary key, \
+            checksum text not null, \
+            applied_at timestamptz not null default (now())\
+        );",
+    )
+    .execute(pool)
+    .await?;
+
+    let mut seen = HashSet::new();
+    for (name, sql) in migrations {
+        if !seen.insert(name) {
+            return Err(ArchivistError::DuplicateMigration(name.into()));
+        }
+
+        apply(pool, name, sql).await?;
+    }
+
+    Ok(())
+}
+
+/// Applies one migration, and records it, in a transaction of its own.
+async fn apply(pool: &Pool<Postgres>, name: &str, sql: &str) -> Result<()> {
+    let checksum = format!("{:x}", Md5::digest(sql));
+
+    let mut transaction = pool.begin().await?;
+    sqlx::query("select pg_advisory_xact_lock($1);")
+        .bind(MIGRATION_LOCK)
+        .execute(&mut *transaction)
+        .await?;
+
+    let applied: Option<(String,)> =
+        sqlx::query_as("select checksum from schema_migrations where name=$1;")
+            .bind(name)
+            .fetch_optional(&mut *transaction)
+            .await?;
+    if let Some((applied,)) = applied {
+        if applied == checksum {
+            debug!("Migration {name} is already applied.");
+        } else {
+            warn!(
+                "Migration {name} has changed since it was applied, and will \
+                not be run again. Put schema changes in a new file."
+            );
+        }
+        return Ok(());
+    }
+
+    info!("Applying migration {name}...");
+    // Sent whole, so the server splits the statements, and semicolons in
+    // strings or function bodies are left alone
+    if !sql.trim().is_empty() {
+        sqlx::raw_sql(sql).execute(&mut *transaction).await?;
+    }
+    sqlx::query(
+        "insert into schema_migrations (name, checksum) values ($1, $2);",
+    )
+    .bind(name)
+    .bind(checksum)
+    .execute(&mut *transaction)
+    .await?;
+
+    Ok(transaction.commit().await?)
+}
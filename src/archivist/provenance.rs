@@ -0,0 +1,54 @@
+//! Tracing a TOA back to everything that went into making it.
+
+use super::{Archivist, Result};
+use crate::data_types::{
+    DiagnosticPlot, ParMeta, ProcessInfo, RawMeta, TOAInfo, TemplateMeta,
+};
+
+/// Everything that went into making a TOA, as found by
+/// [`Archivist::trace_toa`].
+pub struct ToaProvenance {
+    /// The TOA itself.
+    pub toa: TOAInfo,
+    /// The process that made it.
+    pub process: ProcessInfo,
+    /// The raw file it came from.
+    pub raw: RawMeta,
+    /// The template used.
+    pub template: TemplateMeta,
+    /// The ephemeride installed, if any.
+    pub ephemeride: Option<ParMeta>,
+    /// The diagnostic plots made by the process.
+    pub plots: Vec<DiagnosticPlot>,
+}
+
+impl Archivist {
+    /// Gathers the process, raw file, template, ephemeride, and diagnostic
+    /// plots behind the TOA with `toa_id`.
+    ///
+    /// # Errors
+    /// Fails if any of the referenced rows are missing. Forwards errors from
+    /// `sqlx`.
+    pub async fn trace_toa(&self, toa_id: i32) -> Result<ToaProvenance> {
+        let toa = self.get::<TOAInfo>(toa_id).await?;
+        let process = self.get::<ProcessInfo>(toa.process_id).await?;
+        let raw = self.get::<RawMeta>(process.raw_id).await?;
+        let template = self.get::<TemplateMeta>(process.template_id).await?;
+        let ephemeride = match process.par_id {
+            Some(id) => Some(self.get::<ParMeta>(id).await?),
+            None => None,
+        };
+        let plots = self
+            .find_all::<DiagnosticPlot>(&format!("process={}", toa.process_id))
+            .await?;
+
+        Ok(ToaProvenance {
+            toa,
+            process,
+            raw,
+            template,
+            ephemeride,
+            plots,
+        })
+    }
+}
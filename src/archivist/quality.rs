@@ -0,0 +1,171 @@
+//! Queries for quality control of archived data.
+
+use serde::Serialize;
+use sqlx::types::time::OffsetDateTime;
+
+use super::{Archivist, Result, TableItem, table::Table};
+use crate::data_types::TOAInfo;
+
+/// Scales the MAD to the standard deviation for normally distributed data.
+const MAD_TO_SIGMA: f64 = 1.4826;
+
+/// Summary statistics of a float diagnostic, as given by
+/// [`Archivist::diagnostic_stats`]. The statistics are `None` if there are
+/// no values, and `stddev` also if there is just one.
+#[derive(Debug, Clone, sqlx::FromRow, Serialize)]
+pub struct DiagnosticStats {
+    /// The number of values.
+    pub count: i64,
+    /// The smallest value.
+    pub min: Option<f64>,
+    /// The largest value.
+    pub max: Option<f64>,
+    /// The mean of the values.
+    pub mean: Option<f64>,
+    /// The sample standard deviation of the values.
+    pub stddev: Option<f64>,
+}
+
+impl Archivist {
+    /// Finds the TOAs of pulsar `pulsar_id` whose `toa_err` is more than
+    /// `sigma` standard deviations from the pulsar's median. The standard
+    /// deviation is estimated from the median absolute deviation, so a few
+    /// wild errors don't hide each other. Should more than half the errors be
+    /// the same, which makes the MAD 0, the sample standard deviation is used
+    /// instead.
+    ///
+    /// # Errors
+    /// Forwards errors from `sqlx`.
+    pub async fn toa_error_outliers(
+        &self,
+        pulsar_id: i32,
+        sigma: f32,
+    ) -> Result<Vec<TOAInfo>> {
+        let median: (Option<f64>,) = sqlx::query_as(&format!(
+            "select percentile_cont(0.5) within group (order by toa_err) \
+            from {} where pulsar_id=$1;",
+            Table::Toas
+        ))
+        .bind(pulsar_id)
+        .fetch_one(&self.pool)
+        .await?;
+        let Some(median) = median.0 else {
+            // No TOAs with errors
+            return Ok(Vec::new());
+        };
+
+        let (mad, stddev): (f64, Option<f64>) = sqlx::query_as(&format!(
+            "select percentile_cont(0.5) within group \
+            (order by abs(toa_err - $2)), stddev_samp(toa_err) \
+            from {} where pulsar_id=$1;",
+            Table::Toas
+        ))
+        .bind(pulsar_id)
+        .bind(median)
+        .fetch_one(&self.pool)
+        .await?;
+
+        // More than half the errors are the same, so the MAD says nothing
+        let spread = if mad > 0.0 {
+            MAD_TO_SIGMA * mad
+        } else if let Some(stddev) = stddev
+            && stddev > 0.0
+        {
+            stddev
+        } else {
+            // All errors are the same
+            return Ok(Vec::new());
+        };
+
+        let query = format!(
+            "select {} from {} where pulsar_id=$1 and abs(toa_err - $2) > $3;",
+            TOAInfo::select(),
+            Table::Toas,
+        );
+        let outliers = sqlx::query_as(&query)
+            .bind(pulsar_id)
+            .bind(median)
+            .bind(f64::from(sigma) * spread)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(outliers)
+    }
+
+    /// Gets every value of the float diagnostic `diagnostic` for pulsar
+    /// `pulsar_id`, with when it was observed, oldest first. E.g. to follow
+    /// how the SNR of a pulsar changes.
+    ///
+    /// The observation time is that of the earliest TOA of each process, or
+    /// when the raw file was archived for processes without TOAs.
+    ///
+    /// # Errors
+    /// Forwards errors from `sqlx`.
+    pub async fn diagnostic_series(
+        &self,
+        pulsar_id: i32,
+        diagnostic: &str,
+    ) -> Result<Vec<(OffsetDateTime, f32)>> {
+        // MJD 40587 is the Unix epoch
+        let query = format!(
+            "select coalesce(\
+                to_timestamp((min(t.toa_int + t.toa_frac) - 40587) * 86400), \
+                min(r.created_at)\
+            ) as observed, d.result::real \
+            from {} d \
+            join {} p on p.id = d.process \
+            join {} r on r.id = p.raw_id \
+            left join {} t on t.process_id = p.id \
+            where r.pulsar_id=$1 and d.diagnostic=$2 \
+            group by d.id, d.result \
+            order by observed;",
+            Table::DiagnosticFloats,
+            Table::ProcessMetas,
+            Table::RawMetas,
+            Table::Toas
+        );
+
+        let series = sqlx::query_as(&query)
+            .bind(pulsar_id)
+            .bind(diagnostic)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(series)
+    }
+
+    /// Computes summary statistics of the float diagnostic `diagnostic`,
+    /// over all pulsars or only pulsar `pulsar_id`, in the database.
+    ///
+    /// # Errors
+    /// Forwards errors from `sqlx`.
+    pub async fn diagnostic_stats(
+        &self,
+        diagnostic: &str,
+        pulsar_id: Option<i32>,
+    ) -> Result<DiagnosticStats> {
+        let query = format!(
+            "select count(d.result) as count, \
+                min(d.result) as min, \
+                max(d.result) as max, \
+                avg(d.result) as mean, \
+                stddev_samp(d.result) as stddev \
+            from {} d \
+            join {} p on p.id = d.process \
+            join {} r on r.id = p.raw_id \
+            where d.diagnostic=$1 \
+            and ($2::integer is null or r.pulsar_id=$2);",
+            Table::DiagnosticFloats,
+            Table::ProcessMetas,
+            Table::RawMetas,
+        );
+
+        let stats = sqlx::query_as(&query)
+            .bind(diagnostic)
+            .bind(pulsar_id)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(stats)
+    }
+}
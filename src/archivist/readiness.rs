@@ -0,0 +1,149 @@
+//! A structured report on whether the archive is usable.
+
+use std::{
+    process::Stdio,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use log::debug;
+use serde::Serialize;
+use tokio::process::Command;
+
+use super::Archivist;
+use crate::external_tools::psrchive_path;
+
+/// How long psrchive may take to answer when `behaviour.tool_timeout_secs`
+/// sets no limit, so a hung tool can't hang the probe.
+const DEFAULT_TOOL_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Tells apart the files of probes running at the same time.
+static PROBE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Serialize)]
+/// The outcome of [`Archivist::readiness`].
+pub struct ReadinessReport {
+    /// Whether all checks passed.
+    pub ready: bool,
+    /// The individual checks.
+    pub checks: Vec<ReadinessCheck>,
+}
+
+#[derive(Debug, Serialize)]
+/// A single check in a [`ReadinessReport`].
+pub struct ReadinessCheck {
+    /// What was checked.
+    pub name: &'static str,
+    /// Whether it passed.
+    pub passed: bool,
+    /// What went wrong, if anything.
+    pub message: Option<String>,
+    /// How long the check took.
+    pub duration: Duration,
+}
+impl ReadinessCheck {
+    fn timed(
+        name: &'static str,
+        start: Instant,
+        result: Result<(), String>,
+    ) -> Self {
+        debug!("Readiness check '{name}': {result:?}");
+
+        Self {
+            name,
+            passed: result.is_ok(),
+            message: result.err(),
+            duration: start.elapsed(),
+        }
+    }
+}
+
+impl Archivist {
+    /// Checks that the DB is reachable with a `select 1`, returning the round
+    /// trip time. This goes straight to the pool, bypassing any transaction,
+    /// so it is cheap enough for a liveness probe.
+    ///
+    /// # Errors
+    /// Forwards errors from `sqlx`.
+    pub async fn ping(&self) -> super::Result<Duration> {
+        let start = Instant::now();
+        sqlx::query("select 1;").execute(&self.pool).await?;
+
+        Ok(start.elapsed())
+    }
+
+    /// Checks whether the archive is usable, i.e. that
+    ///  - the DB is reachable;
+    ///  - the connection pool is not exhausted;
+    ///  - psrchive can be called; and
+    ///  - the storage and temporary directories are writable.
+    pub async fn readiness(&self) -> ReadinessReport {
+        let mut checks = Vec::with_capacity(5);
+
+        let start = Instant::now();
+        let result =
+            self.ping().await.map(|_| ()).map_err(|err| err.to_string());
+        checks.push(ReadinessCheck::timed("database", start, result));
+
+        let start = Instant::now();
+        let max = self.config.database.pool_connections;
+        let in_use = self.pool.size().saturating_sub(
+            u32::try_from(self.pool.num_idle()).unwrap_or(u32::MAX),
+        );
+        let result = if in_use < max {
+            Ok(())
+        } else {
+            Err(format!("all {max} connections are in use"))
+        };
+        checks.push(ReadinessCheck::timed("pool", start, result));
+
+        let start = Instant::now();
+        let timeout = match self.config.behaviour.tool_timeout_secs {
+            0 => DEFAULT_TOOL_TIMEOUT,
+            secs => Duration::from_secs(secs),
+        };
+        let status = Command::new(psrchive_path(&self.config, "vap"))
+            .arg("-h")
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .status();
+        let result = match tokio::time::timeout(timeout, status).await {
+            Ok(Ok(_)) => Ok(()),
+            Ok(Err(err)) => Err(format!("could not call psrchive::vap: {err}")),
+            Err(_) => Err(format!(
+                "psrchive::vap did not answer within {} s",
+                timeout.as_secs()
+            )),
+        };
+        checks.push(ReadinessCheck::timed("psrchive", start, result));
+
+        for (name, dir) in [
+            ("rawfile_storage", &self.config.paths.rawfile_storage),
+            ("temp_dir", &self.config.paths.temp_dir),
+        ] {
+            let start = Instant::now();
+            let result = check_writable(dir);
+            checks.push(ReadinessCheck::timed(name, start, result));
+        }
+
+        ReadinessReport {
+            ready: checks.iter().all(|c| c.passed),
+            checks,
+        }
+    }
+}
+
+/// Tries to create and remove a file in `dir`, named so that probes running
+/// at the same time don't trip over each other.
+fn check_writable(dir: &str) -> Result<(), String> {
+    let path = format!(
+        "{dir}/.arpa_readiness.{}.{}",
+        std::process::id(),
+        PROBE_COUNTER.fetch_add(1, Ordering::Relaxed)
+    );
+    std::fs::write(&path, [])
+        .and_then(|()| std::fs::remove_file(&path))
+        .map_err(|err| format!("{dir} is not writable: {err}"))
+}
@@ -0,0 +1,93 @@
+//! Review of registry entries that were added automatically.
+
+use log::info;
+
+use super::{Archivist, Result, SqlValue, table::Table};
+
+/// The hook called when something is added automatically.
+pub(super) type AutoAddHook = Box<dyn Fn(&AutoAddEvent) + Send + Sync>;
+
+/// A registry entry added automatically, e.g. a pulsar first seen in a raw
+/// file. Such rows are marked `pending_review` until approved.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum AutoAddEvent {
+    /// A pulsar was added.
+    Pulsar {
+        /// The id of the new row.
+        id: i32,
+        /// The alias it was given.
+        alias: String,
+        /// The raw file it was found in.
+        raw_file: String,
+    },
+}
+impl AutoAddEvent {
+    /// The table the entry was added to.
+    pub const fn table(&self) -> Table {
+        match self {
+            Self::Pulsar { .. } => Table::PulsarMetas,
+        }
+    }
+
+    /// The id of the new row.
+    pub const fn id(&self) -> i32 {
+        match self {
+            Self::Pulsar { id, .. } => *id,
+        }
+    }
+}
+
+impl Archivist {
+    /// Sets a `hook` to be called whenever something is added to the
+    /// registry automatically, e.g. to notify an admin.
+    pub fn set_on_auto_add(
+        &mut self,
+        hook: impl Fn(&AutoAddEvent) + Send + Sync + 'static,
+    ) {
+        self.on_auto_add = Some(Box::new(hook));
+    }
+
+    /// Marks the new row as pending review and calls the hook, if any.
+    pub(crate) async fn auto_added(
+        &mut self,
+        event: AutoAddEvent,
+    ) -> Result<()> {
+        // The row may have been inserted in the live transaction, so it has
+        // to be updated there, without checking the id against the pool
+        let query = format!(
+            "update {} set pending_review=true where id=$1;",
+            event.table()
+        );
+        let tx = self.get_transaction().await?;
+        let result = sqlx::query(&query).bind(event.id()).execute(tx).await;
+        self.finish_implicit(result.map_err(Into::into)).await?;
+
+        if let Some(hook) = &self.on_auto_add {
+            hook(&event);
+        }
+
+        Ok(())
+    }
+
+    /// Approves a row that was added automatically, clearing its
+    /// `pending_review` flag. To reject it, remove the row instead.
+    ///
+    /// # Errors
+    /// Fails if `id` does not exist. Forwards errors from `sqlx`.
+    pub async fn approve_auto_added(
+        &mut self,
+        table: Table,
+        id: i32,
+    ) -> Result<()> {
+        self.update_set(
+            table,
+            id,
+            &[("pending_review", SqlValue::Bool(false))],
+        )
+        .await?;
+        info!("Approved {table} entry {id}");
+
+        Ok(())
+    }
+}
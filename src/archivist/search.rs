@@ -0,0 +1,186 @@
+//! Searches over archived data.
+
+use std::str::FromStr;
+
+use log::warn;
+use psrutils::data_types::{J2000Dec, J2000Ra};
+
+use super::{Archivist, Result, TableItem, table::Table};
+use crate::{
+    conveniences::{separation_arcsec, sexagesimal},
+    data_types::{PulsarMeta, TOAInfo},
+};
+
+/// How much the bands searched in the DB are widened, in degrees, so rounding
+/// does not leave out a pulsar right at the edge.
+const BAND_MARGIN_DEG: f64 = 1e-6;
+
+/// The stored positions the DB converts to degrees when narrowing a search.
+/// Any others are left for [`pulsar_separation`] to warn about.
+const COORD_PATTERN: &str =
+    "^[+-]?[0-9]{1,3}:[0-9]{1,3}:[0-9]{1,3}([.][0-9]*)?$";
+
+impl Archivist {
+    /// Finds the pulsars within `radius_deg` degrees of the position
+    /// `ra`/`dec`, nearest first. Pulsars without a position are left out,
+    /// as are any whose stored position can't be parsed, with a warning.
+    ///
+    /// The rows are first narrowed down in the DB to a band in declination,
+    /// and one in right ascension unless the circle reaches a pole, before
+    /// the exact separation is checked.
+    ///
+    /// # Errors
+    /// Forwards errors from `sqlx`.
+    pub async fn find_pulsars_near(
+        &self,
+        ra: &J2000Ra,
+        dec: &J2000Dec,
+        radius_deg: f64,
+    ) -> Result<Vec<PulsarMeta>> {
+        let ra_deg = sexagesimal(ra.major, ra.minutes, ra.seconds) * 15.0;
+        let dec_deg = sexagesimal(dec.major, dec.minutes, dec.seconds);
+        let radius = radius_deg + BAND_MARGIN_DEG;
+
+        // How far off in right ascension a pulsar within the circle can be
+        let ra_span = if dec_deg.abs() + radius < 90.0 {
+            (radius.to_radians().sin() / dec_deg.to_radians().cos())
+                .asin()
+                .to_degrees()
+        } else {
+            180.0
+        };
+
+        let stored_ra = format!("{} * 15", sexagesimal_sql("j2000_ra"));
+        let stored_dec = sexagesimal_sql("j2000_dec");
+        let query = format!(
+            "select {} from {} \
+            where j2000_ra is not null and j2000_dec is not null \
+            and case when j2000_dec ~ '{COORD_PATTERN}' \
+                then {stored_dec} between $1 and $2 else true end \
+            and case when j2000_ra ~ '{COORD_PATTERN}' \
+                then abs({stored_ra} - $3 \
+                    - 360 * floor(({stored_ra} - $3 + 180) / 360)) <= $4 \
+                else true end;",
+            PulsarMeta::select(),
+            Table::PulsarMetas,
+        );
+        let candidates: Vec<PulsarMeta> = sqlx::query_as(&query)
+            .bind(dec_deg - radius)
+            .bind(dec_deg + radius)
+            .bind(ra_deg)
+            .bind(ra_span)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut near = candidates
+            .into_iter()
+            .filter_map(|pulsar| {
+                let separation = pulsar_separation(&pulsar, ra, dec)?;
+                (separation <= radius_deg * 3600.0)
+                    .then_some((separation, pulsar))
+            })
+            .collect::<Vec<_>>();
+        near.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        Ok(near.into_iter().map(|(_, pulsar)| pulsar).collect())
+    }
+
+    /// Finds the pulsar called `name`, be it by its alias, J name, or B name.
+    /// The name is trimmed and matched case-insensitively. Should several
+    /// pulsars match, the oldest is picked, with a warning.
+    ///
+    /// # Errors
+    /// Forwards errors from `sqlx`.
+    pub async fn find_pulsar_by_any_name(
+        &self,
+        name: &str,
+    ) -> Result<Option<PulsarMeta>> {
+        let query = format!(
+            "select {} from {} \
+            where $1 in (lower(alias), lower(j_name), lower(b_name)) \
+            order by id;",
+            PulsarMeta::select(),
+            Table::PulsarMetas,
+        );
+        let matches: Vec<PulsarMeta> = sqlx::query_as(&query)
+            .bind(name.trim().to_lowercase())
+            .fetch_all(&self.pool)
+            .await?;
+
+        if matches.len() > 1 {
+            warn!(
+                "{} pulsars are called '{}'; picking id {}.",
+                matches.len(),
+                name.trim(),
+                matches[0].id,
+            );
+        }
+
+        Ok(matches.into_iter().next())
+    }
+
+    /// Gets the TOAs of pulsar `pulsar_id` that arrived between the MJDs
+    /// `mjd_start` and `mjd_end`, inclusive, in order of arrival.
+    ///
+    /// # Errors
+    /// Forwards errors from `sqlx`.
+    pub async fn get_toas_in_range(
+        &self,
+        pulsar_id: i32,
+        mjd_start: f64,
+        mjd_end: f64,
+    ) -> Result<Vec<TOAInfo>> {
+        let query = format!(
+            "select {} from {} where pulsar_id=$1 \
+            and toa_int + toa_frac between $2 and $3 \
+            order by toa_int, toa_frac;",
+            TOAInfo::select(),
+            Table::Toas,
+        );
+        let toas = sqlx::query_as(&query)
+            .bind(pulsar_id)
+            .bind(mjd_start)
+            .bind(mjd_end)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(toas)
+    }
+}
+
+/// The separation of `pulsar` from `ra`/`dec`, in arcseconds, if its position
+/// can be parsed.
+fn pulsar_separation(
+    pulsar: &PulsarMeta,
+    ra: &J2000Ra,
+    dec: &J2000Dec,
+) -> Option<f64> {
+    let (Some(p_ra), Some(p_dec)) = (&pulsar.j2000_ra, &pulsar.j2000_dec)
+    else {
+        return None;
+    };
+
+    if let (Ok(p_ra), Ok(p_dec)) =
+        (J2000Ra::from_str(p_ra), J2000Dec::from_str(p_dec))
+    {
+        Some(separation_arcsec(&p_ra, &p_dec, ra, dec))
+    } else {
+        warn!(
+            "Skipping pulsar {} with unreadable position {p_ra} {p_dec}.",
+            pulsar.alias
+        );
+        None
+    }
+}
+
+/// SQL converting the sexagesimal `column` to a decimal number of its major
+/// unit, the same way as [`sexagesimal`]. Only valid for values matching
+/// [`COORD_PATTERN`].
+fn sexagesimal_sql(column: &str) -> String {
+    format!(
+        "(case when split_part({column}, ':', 1)::int < 0 then -1 else 1 end \
+        * (abs(split_part({column}, ':', 1)::int) \
+        + split_part({column}, ':', 2)::float8 / 60 \
+        + split_part({column}, ':', 3)::float8 / 3600))"
+    )
+}
@@ -1,3 +1,5 @@
+use super::{SqlValue, data_types::Checksum};
+
 #[derive(Debug, Clone, Copy)]
 #[allow(missing_docs)]
 pub enum Table {
@@ -14,7 +16,9 @@ pub enum Table {
     ObsSystems,
 
     ProcessMetas,
+    ProcessedMetas,
     DiagnosticFloats,
+    DiagnosticValues,
     DiagnosticPlots,
 }
 impl Table {
@@ -34,7 +38,9 @@ impl Table {
             Self::ObsSystems => "obs_systems",
 
             Self::ProcessMetas => "process_meta",
+            Self::ProcessedMetas => "processed_meta",
             Self::DiagnosticFloats => "diag_floats",
+            Self::DiagnosticValues => "diag_values",
             Self::DiagnosticPlots => "diag_plots",
         }
     }
@@ -71,3 +77,101 @@ pub trait TableItem:
     /// The columns used for selection.
     fn select() -> &'static str;
 }
+
+/// Implemented by items that can be inserted with their values bound as query
+/// parameters, so they need no quoting.
+///
+/// The binds are written by hand, so they name their columns, which must be
+/// those of the derived [`TableItem::insert_columns`], in the same order.
+/// Inserting an item whose binds don't match fails instead of putting values
+/// in the wrong columns.
+/// ```
+/// # use argos_arpa::{InsertBinds, data_types::*};
+/// # use serde::de::DeserializeOwned;
+/// # use serde_json::json;
+/// fn check<T: InsertBinds + DeserializeOwned>(item: serde_json::Value) {
+///     let item: T = serde_json::from_value(item).unwrap();
+///     let columns = T::insert_columns()
+///         .split(',')
+///         .map(str::trim)
+///         .collect::<Vec<_>>();
+///     let binds = item.insert_binds();
+///     let names = binds.iter().map(|(c, _)| *c).collect::<Vec<_>>();
+///     assert_eq!(names, columns, "{}", T::TABLE);
+///     for (column, _) in item.unique_binds() {
+///         assert!(columns.contains(&column), "{}: {column}", T::TABLE);
+///     }
+/// }
+///
+/// let uuid = "00000000-0000-0000-0000-000000000000";
+/// check::<User>(json!({
+///     "id": 0, "username": "u", "real_name": "U", "email": "u@x",
+///     "is_admin": false, "created_at": "2025-01-01T00:00:00Z",
+/// }));
+/// check::<PulsarMeta>(json!({
+///     "id": 0, "alias": "a", "j_name": null, "b_name": null,
+///     "j2000_ra": null, "j2000_dec": null, "master_parfile_id": null,
+/// }));
+/// check::<ParMeta>(json!({
+///     "id": 0, "pulsar_id": 0, "checksum": uuid, "file_path": "p",
+///     "psr_name": null, "f0": null, "f1": null, "dm": null,
+///     "pepoch": null, "binary_model": null,
+/// }));
+/// check::<RawMeta>(json!({
+///     "id": 0, "file_path": "p", "checksum": uuid,
+///     "pulsar_id": 0, "observer_id": 0,
+/// }));
+/// check::<TemplateMeta>(json!({
+///     "id": 0, "pulsar_id": 0, "file_path": "p", "checksum": uuid,
+/// }));
+/// check::<ProcessInfo>(json!({
+///     "id": 0, "raw_id": 0, "par_id": null, "template_id": 0,
+///     "n_channels": 1, "n_subints": 1, "method": "PGS", "user_id": 0,
+/// }));
+/// check::<ProcessedMeta>(json!({
+///     "id": 0, "process_id": 0, "checksum": uuid, "file_path": "p",
+/// }));
+/// check::<TOAInfo>(json!({
+///     "id": 0, "process_id": 0, "template_id": 0, "rawfile_id": 0,
+///     "pulsar_id": 0, "observer_id": 0, "toa_int": 0, "toa_frac": 0.0,
+///     "toa_err": 0.0, "frequency": 0.0, "subint": null, "chan": null,
+/// }));
+/// check::<TelescopeId>(json!({
+///     "id": 0, "name": "t", "abbreviation": "t", "code": "t",
+/// }));
+/// check::<ObsSystem>(json!({
+///     "id": 0, "name": "s", "telescope_id": 0, "frontend": "f",
+///     "backend": "b", "clock": "c", "code": "s",
+/// }));
+/// check::<DiagnosticFloat>(json!({
+///     "id": 0, "process": 0, "diagnostic": "d", "result": 0.0,
+/// }));
+/// check::<DiagnosticValue>(json!({
+///     "id": 0, "process": 0, "diagnostic": "d", "key": "k", "result": 0.0,
+/// }));
+/// check::<DiagnosticPlot>(json!({
+///     "id": 0, "process": 0, "diagnostic": "d", "filepath": "p",
+/// }));
+/// ```
+pub trait InsertBinds: TableItem {
+    /// The values used for insertion, each with its column, in the order of
+    /// [`TableItem::insert_columns`].
+    fn insert_binds(&self) -> Vec<(&'static str, SqlValue)>;
+
+    /// The values of the unique columns, each with its column, used for
+    /// checking conflicts. A `Null` conflicts with nothing.
+    fn unique_binds(&self) -> Vec<(&'static str, SqlValue)>;
+}
+
+/// Implemented by items whose row refers to a file on disk, e.g. ephemerides
+/// and templates.
+pub trait FileItem: TableItem {
+    /// The path of the file this item refers to.
+    fn file_path(&self) -> &str;
+}
+
+/// Implemented by items whose file is tracked with a checksum.
+pub trait ChecksumItem: FileItem {
+    /// The checksum stored for the file.
+    fn checksum(&self) -> Checksum;
+}
@@ -0,0 +1,92 @@
+//! Exporting archived TOAs back to `.tim` files.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::Path,
+};
+
+use log::{info, warn};
+
+use super::{Archivist, ArchivistError, TableItem, table::Table};
+use crate::{
+    Result,
+    data_types::{ObsSystem, RawMeta, TOAInfo},
+};
+
+impl Archivist {
+    /// Writes all TOAs of pulsar `pulsar_id` to a tempo2 `.tim` file at
+    /// `out_path`, in order of arrival, and returns how many there were.
+    /// Give a `process_id` to only export the TOAs of that run.
+    ///
+    /// Each line names the raw file the TOA came from and the site code of
+    /// its obs system.
+    ///
+    /// # Errors
+    /// Fails if the file can't be written. Forwards errors from `sqlx`.
+    pub async fn export_tim(
+        &self,
+        pulsar_id: i32,
+        process_id: Option<i32>,
+        out_path: &str,
+    ) -> Result<usize> {
+        let toas: Vec<TOAInfo> = sqlx::query_as(&format!(
+            "select {} from {} where pulsar_id=$1 \
+            and ($2::integer is null or process_id=$2) \
+            order by toa_int, toa_frac;",
+            TOAInfo::select(),
+            Table::Toas,
+        ))
+        .bind(pulsar_id)
+        .bind(process_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(ArchivistError::from)?;
+
+        if toas.is_empty() {
+            warn!("Pulsar {pulsar_id} has no TOAs to export.");
+        }
+
+        let sites = self
+            .lookup(&toas, |t| t.observer_id, |o: &ObsSystem| o.code().into())
+            .await?;
+        let files = self
+            .lookup(&toas, |t| t.rawfile_id, |r: &RawMeta| file_name(r))
+            .await?;
+
+        let mut content = String::from("FORMAT 1\n");
+        for toa in &toas {
+            content += &toa
+                .to_tim_line(&files[&toa.rawfile_id], &sites[&toa.observer_id]);
+            content.push('\n');
+        }
+        tokio::fs::write(out_path, content).await?;
+
+        info!("Exported {} TOA(s) to {out_path}.", toas.len());
+        Ok(toas.len())
+    }
+
+    /// Gets a value of each distinct item referred to by `toas`, keyed by id.
+    async fn lookup<T>(
+        &self,
+        toas: &[TOAInfo],
+        id: impl Fn(&TOAInfo) -> i32,
+        value: impl Fn(&T) -> String,
+    ) -> Result<HashMap<i32, String>>
+    where
+        T: TableItem,
+    {
+        let ids = toas.iter().map(id).collect::<BTreeSet<_>>();
+        let ids = ids.into_iter().collect::<Vec<_>>();
+        let items = self.get_many::<T>(&ids).await?;
+
+        Ok(ids.into_iter().zip(items.iter().map(value)).collect())
+    }
+}
+
+/// The name of a raw file, without its directory.
+fn file_name(raw: &RawMeta) -> String {
+    Path::new(&raw.file_path).file_name().map_or_else(
+        || raw.file_path.clone(),
+        |name| name.to_string_lossy().into_owned(),
+    )
+}
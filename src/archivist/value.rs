@@ -0,0 +1,143 @@
+//! Typed values that are bound as query parameters, instead of being
+//! formatted into the query string.
+
+use sqlx::{
+    Postgres,
+    postgres::PgArguments,
+    query::Query,
+    types::{time, uuid},
+};
+
+use super::data_types::Checksum;
+
+#[derive(Debug, Clone, PartialEq)]
+#[allow(missing_docs)]
+/// A value to put in a column.
+pub enum SqlValue {
+    Null,
+    Bool(bool),
+    SmallInt(i16),
+    Int(i32),
+    BigInt(i64),
+    Float(f32),
+    Double(f64),
+    Text(String),
+    Uuid(uuid::Uuid),
+    Timestamp(time::OffsetDateTime),
+}
+impl SqlValue {
+    /// Binds this value to the next parameter of `query`. `Null`s are never
+    /// bound, but written as `null` in the query instead, as they have no
+    /// type.
+    pub(crate) fn bind(
+        self,
+        query: Query<'_, Postgres, PgArguments>,
+    ) -> Query<'_, Postgres, PgArguments> {
+        match self {
+            Self::Null => query,
+            Self::Bool(v) => query.bind(v),
+            Self::SmallInt(v) => query.bind(v),
+            Self::Int(v) => query.bind(v),
+            Self::BigInt(v) => query.bind(v),
+            Self::Float(v) => query.bind(v),
+            Self::Double(v) => query.bind(v),
+            Self::Text(v) => query.bind(v),
+            Self::Uuid(v) => query.bind(v),
+            Self::Timestamp(v) => query.bind(v),
+        }
+    }
+}
+
+impl From<bool> for SqlValue {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+impl From<i16> for SqlValue {
+    fn from(value: i16) -> Self {
+        Self::SmallInt(value)
+    }
+}
+impl From<i32> for SqlValue {
+    fn from(value: i32) -> Self {
+        Self::Int(value)
+    }
+}
+impl From<i64> for SqlValue {
+    fn from(value: i64) -> Self {
+        Self::BigInt(value)
+    }
+}
+impl From<f32> for SqlValue {
+    fn from(value: f32) -> Self {
+        Self::Float(value)
+    }
+}
+impl From<f64> for SqlValue {
+    fn from(value: f64) -> Self {
+        Self::Double(value)
+    }
+}
+impl From<String> for SqlValue {
+    fn from(value: String) -> Self {
+        Self::Text(value)
+    }
+}
+impl From<&str> for SqlValue {
+    fn from(value: &str) -> Self {
+        Self::Text(value.to_string())
+    }
+}
+impl From<Checksum> for SqlValue {
+    fn from(value: Checksum) -> Self {
+        Self::Uuid(value.into())
+    }
+}
+impl From<uuid::Uuid> for SqlValue {
+    fn from(value: uuid::Uuid) -> Self {
+        Self::Uuid(value)
+    }
+}
+impl From<time::OffsetDateTime> for SqlValue {
+    fn from(value: time::OffsetDateTime) -> Self {
+        Self::Timestamp(value)
+    }
+}
+impl<T: Into<Self>> From<Option<T>> for SqlValue {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Self::Null, Into::into)
+    }
+}
+
+/// Writes a numbered placeholder for each of `values`, starting after
+/// `offset`, or `null` for `Null`s, as those are never bound. Also returns
+/// how many were numbered.
+pub(super) fn placeholders(
+    values: &[SqlValue],
+    offset: usize,
+) -> (String, usize) {
+    let mut n = offset;
+    let placeholders = values
+        .iter()
+        .map(|value| {
+            if *value == SqlValue::Null {
+                "null".to_string()
+            } else {
+                n += 1;
+                format!("${n}")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    (placeholders, n - offset)
+}
+
+/// Checks that `column` is a plain identifier, as column names can't be bound
+/// as parameters.
+pub(super) fn is_identifier(column: &str) -> bool {
+    column
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && column.chars().next().is_some_and(|c| !c.is_ascii_digit())
+}
@@ -0,0 +1,153 @@
+//! Verifying stored files in the background.
+
+use std::sync::{
+    Arc, Mutex,
+    atomic::{AtomicBool, Ordering},
+};
+
+use log::info;
+use tokio::task::{JoinHandle, JoinSet};
+
+use super::{Archivist, Mismatch, table::ChecksumItem};
+use crate::{ARPAError, config::ChecksumAlgorithm, data_types::Checksum};
+
+/// How far a background verification has come.
+#[derive(Debug, Clone, Default)]
+pub struct VerificationProgress {
+    /// How many files have been checked.
+    pub checked: usize,
+    /// How many files there are to check.
+    pub total: usize,
+    /// The mismatches found so far.
+    pub mismatches: Vec<Mismatch>,
+}
+
+/// A verification running in the background, as started by
+/// [`Archivist::spawn_verification`].
+#[derive(Debug)]
+pub struct VerificationHandle {
+    progress: Arc<Mutex<VerificationProgress>>,
+    cancelled: Arc<AtomicBool>,
+    task: JoinHandle<Result<(), ARPAError>>,
+}
+impl VerificationHandle {
+    /// How far the verification has come.
+    ///
+    /// # Panics
+    /// If the verification panicked while holding the progress.
+    pub fn progress(&self) -> VerificationProgress {
+        self.progress.lock().unwrap().clone()
+    }
+
+    /// Asks the verification to stop. Files already being read are finished,
+    /// but no more are started.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the verification has stopped, either done or cancelled.
+    pub fn is_finished(&self) -> bool {
+        self.task.is_finished()
+    }
+
+    /// Waits for the verification to stop, and returns what it found. If it
+    /// was cancelled, that is only the mismatches found before then.
+    ///
+    /// # Errors
+    /// Fails if a worker can't be joined.
+    ///
+    /// # Panics
+    /// If the verification panicked while holding the progress.
+    pub async fn join(self) -> Result<VerificationProgress, ARPAError> {
+        self.task.await??;
+
+        let mut progress = self.progress.lock().unwrap().clone();
+        progress.mismatches.sort_by_key(|m| m.id);
+        Ok(progress)
+    }
+}
+
+impl Archivist {
+    /// Starts recomputing the checksums of all files in `T::TABLE` in the
+    /// background, with at most `parallelism` files being read at once. The
+    /// returned handle follows its progress and can cancel it.
+    ///
+    /// # Errors
+    /// Forwards errors from `sqlx`.
+    pub async fn spawn_verification<T>(
+        &self,
+        parallelism: usize,
+    ) -> Result<VerificationHandle, ARPAError>
+    where
+        T: ChecksumItem,
+    {
+        let files = self
+            .get_all::<T>()
+            .await?
+            .iter()
+            .map(|item| {
+                (item.id(), item.file_path().to_string(), item.checksum())
+            })
+            .collect::<Vec<_>>();
+        info!("Verifying {} file(s) in {}...", files.len(), T::TABLE);
+
+        let progress = Arc::new(Mutex::new(VerificationProgress {
+            total: files.len(),
+            ..Default::default()
+        }));
+        let cancelled = Arc::new(AtomicBool::new(false));
+
+        let task = tokio::spawn(verify(
+            files,
+            self.config().behaviour.checksum_algorithm,
+            parallelism.max(1),
+            Arc::clone(&progress),
+            Arc::clone(&cancelled),
+        ));
+
+        Ok(VerificationHandle {
+            progress,
+            cancelled,
+            task,
+        })
+    }
+}
+
+/// Checks the `files`, keeping `progress` up to date, until done or
+/// `cancelled`.
+async fn verify(
+    files: Vec<(i32, String, Checksum)>,
+    algorithm: ChecksumAlgorithm,
+    parallelism: usize,
+    progress: Arc<Mutex<VerificationProgress>>,
+    cancelled: Arc<AtomicBool>,
+) -> Result<(), ARPAError> {
+    let record = |mismatch: Option<Mismatch>| {
+        let mut progress = progress.lock().unwrap();
+        progress.checked += 1;
+        progress.mismatches.extend(mismatch);
+    };
+
+    let mut tasks = JoinSet::new();
+    for (id, file_path, expected) in files {
+        if cancelled.load(Ordering::Relaxed) {
+            info!("Verification cancelled.");
+            break;
+        }
+
+        if tasks.len() >= parallelism
+            && let Some(result) = tasks.join_next().await
+        {
+            record(result?);
+        }
+
+        tasks.spawn_blocking(move || {
+            Mismatch::check(id, file_path, expected, algorithm)
+        });
+    }
+    while let Some(result) = tasks.join_next().await {
+        record(result?);
+    }
+
+    Ok(())
+}
@@ -1,9 +1,9 @@
 //! A collection of convenience funtions. Might dissolve into separate modules
 //! in the future.
 
-use crate::{ARPAError, Result};
+use crate::{ARPAError, Result, config::ChecksumAlgorithm};
 use std::{
-    any::type_name,
+    any::{Any, type_name},
     fs::File,
     io::{BufReader, Read, Write, stdout},
     os::unix::fs::MetadataExt,
@@ -14,12 +14,14 @@ use std::{
 
 use log::{info, warn};
 use md5::Digest;
+use psrutils::data_types::{J2000Dec, J2000Ra};
 
 /// The number of bytes to buffer when reading checksums.
 ///
 /// FYI, changing this after deployment will break compatibility with any
 /// previous files. This is why it is kept as a constant instead of in the
-/// config.
+/// config. (The same goes for `behaviour.checksum_algorithm`, which is in the
+/// config only so it can be picked before deployment.)
 const BLOCK_SIZE: usize = 16 * 16 * 8192;
 
 /// Checks a path for a file.
@@ -33,6 +35,31 @@ pub fn assert_exists(path: &str) -> Result<()> {
     }
 }
 
+/// Checks a path for a file, making sure it is not e.g. a directory.
+/// # Errors
+/// The file does not exist, is not a file, or there is an io problem.
+pub fn assert_file_exists(path: &str) -> Result<()> {
+    assert_exists(path)?;
+    if !std::fs::metadata(path)?.is_file() {
+        return Err(ARPAError::NotAFile(path.into()));
+    }
+
+    Ok(())
+}
+
+/// Checks a path for a directory, making sure it is not e.g. a file.
+/// # Errors
+/// The directory does not exist, is not a directory, or there is an io
+/// problem.
+pub fn assert_dir_exists(path: &str) -> Result<()> {
+    assert_exists(path)?;
+    if !std::fs::metadata(path)?.is_dir() {
+        return Err(ARPAError::NotADirectory(path.into()));
+    }
+
+    Ok(())
+}
+
 #[allow(
     clippy::cast_possible_truncation,
     clippy::cast_sign_loss,
@@ -59,7 +86,85 @@ pub fn progress_bar(message: &str, progress: f32, size: usize) {
     _ = stdout().flush();
 }
 
+/// Where progress of long tasks, e.g. checksumming, is reported.
+pub trait ProgressReporter: Send {
+    /// Reports that the task described by `message` is at `progress`, from 0
+    /// to 1.
+    fn progress(&mut self, message: &str, progress: f32);
+
+    /// Reports that the task described by `message` finished after `elapsed`.
+    fn finish(&mut self, message: &str, elapsed: Duration);
+}
+
+/// Reports nothing.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoProgress;
+impl ProgressReporter for NoProgress {
+    fn progress(&mut self, _: &str, _: f32) {}
+    fn finish(&mut self, _: &str, _: Duration) {}
+}
+
+/// Draws a [`progress_bar`] on stdout. This needs a terminal with a Nerd
+/// Font, so it is only for interactive use.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TerminalReporter;
+impl ProgressReporter for TerminalReporter {
+    fn progress(&mut self, message: &str, progress: f32) {
+        progress_bar(message, progress, 32);
+    }
+
+    fn finish(&mut self, _: &str, elapsed: Duration) {
+        println!("\nDone in {:<32}", display_elapsed_time(elapsed));
+    }
+}
+
+/// Logs the progress every `step` percent, for running without a terminal.
+#[derive(Debug, Clone, Copy)]
+pub struct LogReporter {
+    step: u32,
+    next: u32,
+}
+impl LogReporter {
+    /// A reporter logging every `step` percent.
+    pub fn new(step: u32) -> Self {
+        Self {
+            step: step.max(1),
+            next: 0,
+        }
+    }
+}
+impl Default for LogReporter {
+    fn default() -> Self {
+        Self::new(10)
+    }
+}
+impl ProgressReporter for LogReporter {
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn progress(&mut self, message: &str, progress: f32) {
+        let percent = (progress.clamp(0.0, 1.0) * 100.0) as u32;
+        if percent >= self.next {
+            info!("{message} {percent}%");
+            self.next = (percent / self.step + 1) * self.step;
+        }
+    }
+
+    fn finish(&mut self, message: &str, elapsed: Duration) {
+        info!("{message} done in {}", display_elapsed_time(elapsed));
+        self.next = 0;
+    }
+}
+
 /// Forms a string from the elapsed time, mainly to get easily readable times.
+///
+/// E.g.
+/// ```
+/// # use argos_arpa::conveniences::display_elapsed_time;
+/// # use std::time::Duration;
+/// assert_eq!(display_elapsed_time(Duration::from_millis(1500)), "1 s");
+/// assert_eq!(display_elapsed_time(Duration::from_secs(185)), "3 m 5 s");
+/// assert_eq!(display_elapsed_time(Duration::from_secs(10_805)), "3 h 0 m 5 s");
+/// assert_eq!(display_elapsed_time(Duration::from_secs(97_205)), "1 d 3 h");
+/// ```
 pub fn display_elapsed_time(duration: Duration) -> String {
     let micros = duration.as_micros();
 
@@ -77,10 +182,23 @@ pub fn display_elapsed_time(duration: Duration) -> String {
         return format!("{seconds} s");
     }
 
-    let minutes = seconds / 60;
+    let mut minutes = seconds / 60;
     seconds -= 60 * minutes;
+    if minutes < 60 {
+        return format!("{minutes} m {seconds} s");
+    }
+
+    let mut hours = minutes / 60;
+    minutes -= 60 * hours;
+    if hours < 24 {
+        return format!("{hours} h {minutes} m {seconds} s");
+    }
 
-    format!("{minutes} m {seconds} s")
+    // At this scale, the minutes hardly matter
+    let days = hours / 24;
+    hours -= 24 * days;
+
+    format!("{days} d {hours} h")
 }
 
 #[allow(clippy::missing_errors_doc)]
@@ -93,6 +211,83 @@ where
         .map_err(|_| ARPAError::ParseFailed(text.to_string(), type_name::<T>()))
 }
 
+/// Forms the stem psrchive uses when naming files of the pulsar `name`,
+/// observed at `intmjd` + `fracmjd`, i.e. the name, the day, and the second of
+/// that day.
+///
+/// The seconds are truncated, not rounded, to agree with psrchive. E.g.
+/// ```
+/// # use argos_arpa::conveniences::psrchive_stem;
+/// assert_eq!(
+///     psrchive_stem("J0437-4715", 60000, 0.5).unwrap(),
+///     "J0437-4715_60000_43200",
+/// );
+/// assert_eq!(
+///     psrchive_stem("J0437-4715", 800, 0.999_999).unwrap(),
+///     "J0437-4715_00800_86399",
+/// );
+/// assert!(psrchive_stem("J0437-4715", 60000, 1.0).is_err());
+/// ```
+///
+/// # Errors
+/// Fails if `fracmjd` is not in [0, 1).
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub fn psrchive_stem(name: &str, intmjd: u32, fracmjd: f64) -> Result<String> {
+    if !(0.0..1.0).contains(&fracmjd) {
+        return Err(ARPAError::MalformedInput(format!(
+            "fracmjd {fracmjd} is not in [0, 1)"
+        )));
+    }
+    let secs = (fracmjd * 86_400.0).floor() as u32;
+
+    Ok(format!("{name}_{intmjd:05}_{secs:05}"))
+}
+
+/// The angle between two J2000 positions on the sky, in arcseconds.
+pub fn separation_arcsec(
+    ra_a: &J2000Ra,
+    dec_a: &J2000Dec,
+    ra_b: &J2000Ra,
+    dec_b: &J2000Dec,
+) -> f64 {
+    let ra_a = (sexagesimal(ra_a.major, ra_a.minutes, ra_a.seconds) * 15.0)
+        .to_radians();
+    let ra_b = (sexagesimal(ra_b.major, ra_b.minutes, ra_b.seconds) * 15.0)
+        .to_radians();
+    let dec_a =
+        sexagesimal(dec_a.major, dec_a.minutes, dec_a.seconds).to_radians();
+    let dec_b =
+        sexagesimal(dec_b.major, dec_b.minutes, dec_b.seconds).to_radians();
+
+    // The haversine formula, which behaves well for small angles
+    let h = (dec_a.cos() * dec_b.cos()).mul_add(
+        ((ra_b - ra_a) / 2.0).sin().powi(2),
+        ((dec_b - dec_a) / 2.0).sin().powi(2),
+    );
+
+    (2.0 * h.sqrt().asin()).to_degrees() * 3600.0
+}
+
+/// Combines a sexagesimal coordinate, applying the sign of `major` to all of
+/// it.
+pub(crate) fn sexagesimal(major: i8, minutes: u8, seconds: f64) -> f64 {
+    let magnitude = seconds.mul_add(
+        1.0 / 3600.0,
+        f64::from(major.unsigned_abs()) + f64::from(minutes) / 60.0,
+    );
+
+    if major < 0 { -magnitude } else { magnitude }
+}
+
+/// The message a panic was raised with, if it was a string.
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(ToString::to_string)
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_default()
+}
+
 /// Forms a string with comma separated digit triples.
 ///
 /// E.g.
@@ -102,13 +297,49 @@ where
 /// assert_eq!(comma_separate(&1234u64),   "1,234");
 /// assert_eq!(comma_separate(&12345u64),  "12,345");
 /// assert_eq!(comma_separate(&123456u64), "123,456");
+/// assert_eq!(comma_separate(&-1234i64),  "-1,234");
+/// assert_eq!(comma_separate(&-123i32),   "-123");
 /// ```
 pub fn comma_separate<T>(value: &T) -> String
 where
-    T: Into<u64> + ToString,
+    T: Into<i128> + Copy,
 {
-    value
-        .to_string()
+    let value: i128 = (*value).into();
+    let digits = group_digits(&value.unsigned_abs().to_string());
+    if value < 0 {
+        format!("-{digits}")
+    } else {
+        digits
+    }
+}
+
+/// Forms a string with comma separated digit triples in the integer part,
+/// keeping `decimals` decimal places.
+///
+/// E.g.
+/// ```
+/// # use argos_arpa::conveniences::comma_separate_float;
+/// assert_eq!(comma_separate_float(1234.5678, 2),  "1,234.57");
+/// assert_eq!(comma_separate_float(-1234567.0, 1), "-1,234,567.0");
+/// assert_eq!(comma_separate_float(999.9, 0),      "1,000");
+/// assert_eq!(comma_separate_float(-0.25, 3),      "-0.250");
+/// ```
+pub fn comma_separate_float(value: f64, decimals: usize) -> String {
+    if !value.is_finite() {
+        return value.to_string();
+    }
+
+    let text = format!("{:.decimals$}", value.abs());
+    let (integer, fraction) =
+        text.split_at(text.find('.').unwrap_or(text.len()));
+    let sign = if value < 0.0 { "-" } else { "" };
+
+    format!("{sign}{}{fraction}", group_digits(integer))
+}
+
+/// Puts commas between the digit triples of `digits`, counted from the end.
+fn group_digits(digits: &str) -> String {
+    digits
         .chars()
         .rev()
         .collect::<Vec<_>>()
@@ -128,14 +359,81 @@ where
         .fold(String::new(), |a, d| a + &d)
 }
 
-#[allow(clippy::cast_precision_loss)]
-/// Computes the MD5 checksum of a file.
+/// Computes the checksum of a file, with `algorithm` as configured, sending
+/// the progress to `reporter`.
+///
+/// With MD5, this agrees with `md5sum`, also for files that don't fill the
+/// last block. SHA-256 is truncated to its first 128 bits, i.e. the first 32
+/// hex digits of `sha256sum`. E.g.
+/// ```
+/// # use argos_arpa::conveniences::{compute_checksum, NoProgress};
+/// # use argos_arpa::config::ChecksumAlgorithm;
+/// let path = std::env::temp_dir().join("arpa_checksum_doctest.bin");
+/// let data = (0..3 * 1024 * 1024 + 7)
+///     .map(|i| (i % 251) as u8)
+///     .collect::<Vec<_>>();
+/// std::fs::write(&path, data).unwrap();
+///
+/// // As given by `md5sum`
+/// assert_eq!(
+///     compute_checksum(&path, ChecksumAlgorithm::Md5, &mut NoProgress)
+///         .unwrap(),
+///     0x62a2_2cb2_45a4_cfdb_1a8a_8ba5_79b2_f38a,
+/// );
+/// # std::fs::remove_file(&path).unwrap();
+/// let path = std::env::temp_dir().join("arpa_checksum_doctest_sha.bin");
+/// std::fs::write(&path, b"abc").unwrap();
+///
+/// // The first half of `sha256sum`
+/// assert_eq!(
+///     compute_checksum(&path, ChecksumAlgorithm::Sha256, &mut NoProgress)
+///         .unwrap(),
+///     0xba78_16bf_8f01_cfea_4141_40de_5dae_2223,
+/// );
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
 ///
 /// # Errors
 /// Possible io failure.
 pub fn compute_checksum(
     path: impl AsRef<Path>,
-    verbose: bool,
+    algorithm: ChecksumAlgorithm,
+    reporter: &mut dyn ProgressReporter,
+) -> std::io::Result<u128> {
+    match algorithm {
+        ChecksumAlgorithm::Md5 => {
+            hash_file::<md5::Md5>(path, algorithm.name(), reporter)
+        }
+        ChecksumAlgorithm::Sha256 => {
+            hash_file::<sha2::Sha256>(path, algorithm.name(), reporter)
+        }
+    }
+}
+
+/// Same as [`compute_checksum`], but reads the file on tokio's blocking
+/// threads, so it does not hold up the async runtime.
+///
+/// # Errors
+/// Possible io failure, or the blocking task panicking.
+pub async fn compute_checksum_async(
+    path: impl AsRef<Path>,
+    algorithm: ChecksumAlgorithm,
+    mut reporter: Box<dyn ProgressReporter>,
+) -> std::io::Result<u128> {
+    let path = path.as_ref().to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        compute_checksum(path, algorithm, reporter.as_mut())
+    })
+    .await
+    .map_err(std::io::Error::other)?
+}
+
+#[allow(clippy::cast_precision_loss)]
+/// Hashes a file with `D`, keeping the first 128 bits of the digest.
+fn hash_file<D: Digest>(
+    path: impl AsRef<Path>,
+    name: &str,
+    reporter: &mut dyn ProgressReporter,
 ) -> std::io::Result<u128> {
     let t0 = Instant::now();
 
@@ -143,56 +441,50 @@ pub fn compute_checksum(
     let size = file.metadata()?.size();
     let mut reader = BufReader::new(file);
 
-    let mut hasher = md5::Md5::new();
+    let mut hasher = D::new();
+    let message = format!("Computing {name} checksum...");
 
     // To show progress
     let len = (size as f32 / BLOCK_SIZE as f32).max(1.0);
     let mut read = 0.0;
 
     let mut buffer = vec![0u8; BLOCK_SIZE];
-    while reader.read(&mut buffer)? > 0 {
-        hasher.update(&buffer);
-
-        read += 1.0;
-        if verbose {
-            progress_bar("Computing MD5 checksum...", read / len, 32);
+    loop {
+        let n = reader.read(&mut buffer)?;
+        if n == 0 {
+            break;
         }
-    }
+        hasher.update(&buffer[..n]);
 
-    if verbose {
-        println!("\nDone in {:<32}", display_elapsed_time(t0.elapsed()),);
+        read += 1.0;
+        reporter.progress(&message, read / len);
     }
+    reporter.finish(&message, t0.elapsed());
 
     let hash = hasher
         .finalize()
         .iter()
+        .take(16)
         .fold(0, |a, b| (a << 8) + u128::from(*b));
 
     Ok(hash)
 }
 
-pub(crate) fn check_file_equality(source: &str, path: String) -> Result<u128> {
+pub(crate) async fn check_file_equality(
+    source: &str,
+    path: String,
+    algorithm: ChecksumAlgorithm,
+    reporter: Box<dyn ProgressReporter>,
+) -> Result<u128> {
     warn!("File already exists: '{path}'! Will not overwrite.");
-    let src_size = File::open(source)?.metadata()?.size();
-    let dst_size = File::open(&path)?.metadata()?.size();
-
-    if src_size != dst_size {
-        warn!("Old file is {dst_size} bytes and new is {src_size} bytes.");
+    if !file_sizes_match(source, &path)? {
         return Ok(0);
     }
 
-    let sc = source.to_string();
-    let src_checksum_handle =
-        std::thread::spawn(move || compute_checksum(sc, true));
-    let dst_checksum_handle =
-        std::thread::spawn(move || compute_checksum(path, false));
-
-    let src_checksum = src_checksum_handle
-        .join()
-        .map_err(|err| ARPAError::JoinThread(format!("{err:?}")))??;
-    let dst_checksum = dst_checksum_handle
-        .join()
-        .map_err(|err| ARPAError::JoinThread(format!("{err:?}")))??;
+    let (src_checksum, dst_checksum) = tokio::try_join!(
+        compute_checksum_async(source, algorithm, reporter),
+        compute_checksum_async(&path, algorithm, Box::new(NoProgress)),
+    )?;
 
     if src_checksum == dst_checksum {
         info!(
@@ -203,3 +495,15 @@ pub(crate) fn check_file_equality(source: &str, path: String) -> Result<u128> {
 
     Ok(src_checksum)
 }
+
+/// Compares the sizes of two files, without reading them.
+pub(crate) fn file_sizes_match(source: &str, path: &str) -> Result<bool> {
+    let src_size = File::open(source)?.metadata()?.size();
+    let dst_size = File::open(path)?.metadata()?.size();
+
+    if src_size != dst_size {
+        warn!("Old file is {dst_size} bytes and new is {src_size} bytes.");
+    }
+
+    Ok(src_size == dst_size)
+}
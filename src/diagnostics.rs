@@ -1,20 +1,108 @@
 //! Diagnostic tools for the pipeline.
 
-use crate::data_types::{DiagnosticFloat, DiagnosticPlot, archive_file};
+use std::collections::BTreeMap;
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::sync::Arc;
+
+use log::{info, warn};
+
+use crate::config::Config;
+use crate::conveniences::panic_message;
+use crate::data_types::{DiagnosticFloat, DiagnosticPlot, DiagnosticValue};
 use crate::{ARPAError, Archivist, Result};
 
+mod bandpass;
 mod composite;
+mod rfi;
 mod snr;
 
-/// The value of a diagnostic tool's output, either a plot or a float for now.
+/// The value of a diagnostic tool's output, either a plot, a float, or
+/// several keyed floats.
 pub enum DiagnosticOut {
     /// A plot, with the inner argument being the path.
     Plot(String),
     /// A float value.
     Value(f32),
+    /// Several float values, each with a key telling them apart, e.g. one
+    /// per channel.
+    Values(Vec<(String, f32)>),
+}
+
+/// A diagnostic that can be run on a processed file. Implement this to add
+/// your own, and register it with [`DiagnosticRegistry::register`].
+pub trait Diagnostic: Send + Sync {
+    /// The name the diagnostic is stored and configured under.
+    fn name(&self) -> &str;
+
+    /// Runs the diagnostic on `file`. Any intermediate files go in
+    /// `work_dir`, which is shared with other diagnostics running at the same
+    /// time, so name them after the diagnostic.
+    ///
+    /// # Errors
+    /// Whatever goes wrong with the diagnostic.
+    fn run(
+        &self,
+        config: &Config,
+        file: &str,
+        work_dir: &str,
+    ) -> Result<DiagnosticOut>;
+}
+
+/// The diagnostics that can be run, by name. The default one has the
+/// built-in diagnostics `snr`, `composite`, `bandpass`, and `rfi_fraction`.
+pub struct DiagnosticRegistry {
+    diagnostics: BTreeMap<String, Arc<dyn Diagnostic>>,
 }
+impl DiagnosticRegistry {
+    /// A registry without any diagnostics.
+    pub fn empty() -> Self {
+        Self {
+            diagnostics: BTreeMap::new(),
+        }
+    }
 
-/// Runs an indicated diagnostic function and stores the result.
+    /// Adds a diagnostic, replacing any other with the same name.
+    pub fn register(&mut self, diagnostic: Box<dyn Diagnostic>) {
+        self.diagnostics
+            .insert(diagnostic.name().to_string(), Arc::from(diagnostic));
+    }
+
+    /// The diagnostic called `name`, if registered.
+    pub fn get(&self, name: &str) -> Option<&dyn Diagnostic> {
+        self.diagnostics.get(name).map(AsRef::as_ref)
+    }
+
+    /// The diagnostic called `name`, if registered, to run off the registry,
+    /// e.g. on a blocking task.
+    pub fn get_shared(&self, name: &str) -> Option<Arc<dyn Diagnostic>> {
+        self.diagnostics.get(name).cloned()
+    }
+
+    /// The names of all registered diagnostics.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.diagnostics.keys().map(String::as_str)
+    }
+}
+impl Default for DiagnosticRegistry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry.register(Box::new(snr::Snr));
+        registry.register(Box::new(composite::Composite));
+        registry.register(Box::new(bandpass::Bandpass));
+        registry.register(Box::new(rfi::RfiFraction));
+        registry
+    }
+}
+impl std::fmt::Debug for DiagnosticRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.names()).finish()
+    }
+}
+
+/// Runs an indicated diagnostic function and stores the result. Plots end up
+/// in `directory`, and any intermediate files in `work_dir`.
+///
+/// Returns the path of the plot, if the diagnostic made one.
 /// # Errors
 /// Fails if the diagnositc tool fails, or the `archivist` can't do its thing.
 pub async fn run_diagnostic(
@@ -23,19 +111,74 @@ pub async fn run_diagnostic(
     process: i32,
     file: &str,
     directory: &str,
-) -> Result<()> {
-    let out = match diagnostic {
-        "snr" => snr::run(archivist.config(), file),
-        "composite" => composite::run(archivist.config(), file),
+    work_dir: &str,
+) -> Result<Option<String>> {
+    let out = compute_diagnostic(
+        archivist.config(),
+        archivist.diagnostics(),
+        diagnostic,
+        file,
+        work_dir,
+    )?;
+    store_diagnostic(archivist, diagnostic, process, out, directory).await
+}
 
-        other => Err(ARPAError::UnknownDiagnostic(other.to_string())),
-    }?;
+/// Runs an indicated diagnostic from `registry`, without storing the result.
+/// This does not touch the database, so several may run at once.
+///
+/// # Errors
+/// Fails if the diagnostic is unknown, or its tool fails or panics.
+pub fn compute_diagnostic(
+    config: &Config,
+    registry: &DiagnosticRegistry,
+    diagnostic: &str,
+    file: &str,
+    work_dir: &str,
+) -> Result<DiagnosticOut> {
+    let Some(tool) = registry.get(diagnostic) else {
+        return Err(ARPAError::UnknownDiagnostic(diagnostic.to_string()));
+    };
+    run_tool(config, tool, file, work_dir)
+}
+
+/// Runs `tool` on `file`, without storing the result.
+///
+/// # Errors
+/// Fails if the tool fails or panics.
+pub fn run_tool(
+    config: &Config,
+    tool: &dyn Diagnostic,
+    file: &str,
+    work_dir: &str,
+) -> Result<DiagnosticOut> {
+    // A panicking tool must not unwind through the archivist's live
+    // transaction, so it is turned into a regular error here.
+    catch_unwind(AssertUnwindSafe(|| tool.run(config, file, work_dir)))
+        .map_err(|payload| {
+            ARPAError::DiagnosticPanicked(
+                tool.name().to_string(),
+                panic_message(payload.as_ref()),
+            )
+        })?
+}
 
-    match out {
-        DiagnosticOut::Plot(mut path) => {
-            _ = archive_file(
+/// Stores the output of a diagnostic, placing any plot in `directory`.
+///
+/// Returns the path of the plot, if the diagnostic made one.
+/// # Errors
+/// Fails if the plot can't be placed, or the `archivist` can't do its thing.
+pub async fn store_diagnostic(
+    archivist: &mut Archivist,
+    diagnostic: &str,
+    process: i32,
+    out: DiagnosticOut,
+    directory: &str,
+) -> Result<Option<String>> {
+    let plot = match out {
+        DiagnosticOut::Plot(source) => {
+            let path = place_plot(
                 archivist.config(),
-                &mut path,
+                &source,
                 directory,
                 &format!("{diagnostic}.png"),
             )?;
@@ -44,10 +187,11 @@ pub async fn run_diagnostic(
                 id: 0,
                 process,
                 diagnostic: diagnostic.to_string(),
-                filepath: path,
+                filepath: path.clone(),
             };
 
             archivist.insert(meta).await?;
+            Some(path)
         }
         DiagnosticOut::Value(result) => {
             let meta = DiagnosticFloat {
@@ -58,8 +202,58 @@ pub async fn run_diagnostic(
             };
 
             archivist.insert(meta).await?;
+            None
+        }
+        DiagnosticOut::Values(values) => {
+            let metas = values
+                .into_iter()
+                .map(|(key, result)| DiagnosticValue {
+                    id: 0,
+                    process,
+                    diagnostic: diagnostic.to_string(),
+                    key,
+                    result,
+                })
+                .collect();
+
+            archivist.insert_many(metas).await?;
+            None
         }
+    };
+
+    Ok(plot)
+}
+
+/// Moves a freshly made plot from `source` into `directory` as `name`.
+///
+/// Unlike raw files, plots are not checked for equality: a re-run replaces
+/// the old plot, unless configured to keep it. The plot is first copied to a
+/// unique name next to its destination and then renamed, so the destination
+/// is never half-written.
+fn place_plot(
+    config: &Config,
+    source: &str,
+    directory: &str,
+    name: &str,
+) -> Result<String> {
+    let path = format!("{directory}/{name}");
+    std::fs::create_dir_all(directory)?;
+
+    if config.behaviour.keep_existing_diagnostics && std::fs::exists(&path)? {
+        warn!("Keeping existing plot {path}.");
+    } else {
+        let tmp = format!("{directory}/.{name}.{}.tmp", std::process::id());
+        std::fs::copy(source, &tmp)?;
+        if let Err(err) = std::fs::rename(&tmp, &path) {
+            _ = std::fs::remove_file(&tmp);
+            return Err(err.into());
+        }
+        info!("Placed plot at {path}");
+    }
+
+    if let Err(err) = std::fs::remove_file(source) {
+        warn!("Could not remove temporary plot {source}: {err}");
     }
 
-    Ok(())
+    Ok(path)
 }
@@ -0,0 +1,66 @@
+//! Bandpass plot, i.e. power against frequency, using `psrchive::psrplot`.
+use log::info;
+
+use super::{Diagnostic, DiagnosticOut};
+use crate::config::Config;
+use crate::conveniences::assert_file_exists;
+use crate::data_types::RawFileHeader;
+use crate::external_tools::psrchive;
+use crate::{ARPAError, Result};
+
+/// The `bandpass` diagnostic.
+pub struct Bandpass;
+impl Diagnostic for Bandpass {
+    fn name(&self) -> &'static str {
+        "bandpass"
+    }
+
+    fn run(
+        &self,
+        config: &Config,
+        file: &str,
+        work_dir: &str,
+    ) -> Result<DiagnosticOut> {
+        run(config, file, work_dir)
+    }
+}
+
+/// Tries to create a bandpass plot, to spot channels with RFI.
+///
+/// # Errors
+/// Fails if the file is unreadable, has only one channel, or the plotter
+/// fails.
+pub fn run(
+    config: &Config,
+    file: &str,
+    work_dir: &str,
+) -> Result<DiagnosticOut> {
+    info!("Creating bandpass plot for {file}...");
+
+    let header = RawFileHeader::get_cached(config, file)?;
+    if header.channel_count < 2 {
+        return Err(ARPAError::DiagnosticSingleChannel(
+            "bandpass".into(),
+            file.to_string(),
+        ));
+    }
+
+    let tmp = format!("{work_dir}/bandpass.png");
+    let tmpcmd = format!("{tmp}/PNG");
+    let args = [
+        "-j",
+        "T",
+        file,
+        "-D",
+        &tmpcmd,
+        "-p",
+        "b",
+        "-c",
+        "pol=I,y:lab=Power,x:lab=Frequency (MHz)",
+    ];
+    _ = psrchive(config, "psrplot", &args)?;
+
+    assert_file_exists(&tmp)?;
+
+    Ok(DiagnosticOut::Plot(tmp))
+}
@@ -1,28 +1,49 @@
 use log::info;
 
-use super::DiagnosticOut;
+use super::{Diagnostic, DiagnosticOut};
 use crate::config::Config;
-use crate::conveniences::assert_exists;
+use crate::conveniences::assert_file_exists;
 use crate::data_types::RawFileHeader;
 use crate::external_tools::psrchive;
 use crate::{ARPAError, Result};
 
+/// The `composite` diagnostic.
+pub struct Composite;
+impl Diagnostic for Composite {
+    fn name(&self) -> &'static str {
+        "composite"
+    }
+
+    fn run(
+        &self,
+        config: &Config,
+        file: &str,
+        work_dir: &str,
+    ) -> Result<DiagnosticOut> {
+        run(config, file, work_dir)
+    }
+}
+
 /// Tries to create diagnostic plots.
 ///
 /// # Errors
 /// Fails if the fils is unreadable or the plotter fails.
-pub fn run(config: &Config, file: &str) -> Result<DiagnosticOut> {
+pub fn run(
+    config: &Config,
+    file: &str,
+    work_dir: &str,
+) -> Result<DiagnosticOut> {
     info!("Creating composite plots for {file}...");
 
     let fname = file.rfind('/').map_or(file, |i| &file[i + 1..]);
-    let tmp = format!("{}/tmp.png", config.paths.temp_dir);
+    let tmp = format!("{work_dir}/composite.png");
     let tmpcmd = format!("{tmp}/PNG");
-    let header = RawFileHeader::get(config, file)?;
+    let header = RawFileHeader::get_cached(config, file)?;
     let info = format!(
-        "above:l='{}\n\
+        "above:l={}\n\
         {}    {} ({})\n\
         Length={:.1} s    BW={:.1} MHz\n\
-        N\\dbin\\u=$nbin    N\\dchan\\u=$nchan    N\\dsub\\u=$nsubint',\
+        N\\dbin\\u=$nbin    N\\dchan\\u=$nchan    N\\dsub\\u=$nsubint,\
         above:off=3.5",
         fname,
         header.telescope,
@@ -42,7 +63,7 @@ pub fn run(config: &Config, file: &str) -> Result<DiagnosticOut> {
         (false, false) => plot_prof_only(config, file, &tmpcmd, &info)?,
     }
 
-    assert_exists(&tmp)?;
+    assert_file_exists(&tmp)?;
 
     Ok(DiagnosticOut::Plot(tmp))
 }
@@ -65,31 +86,33 @@ fn plot_all(
         "-p",
         "flux",
         "-c",
-        ":0:x:view=0.575:0.95,",
-        "y:view=0.7:0.9,",
-        "subint=I,",
-        "chan=I,",
-        "pol=I,",
-        "x:opt=BCTS,",
-        "x:lab=,",
-        "below:l=",
+        ":0:x:view=0.575:0.95,\
+        y:view=0.7:0.9,\
+        subint=I,\
+        chan=I,\
+        pol=I,\
+        x:opt=BCTS,\
+        x:lab=,\
+        below:l=",
         "-p",
         "freq",
         "-c",
-        ":1:x:view=0.075:0.45,",
-        "y:view=0.15:0.7,",
-        "subint=I,",
-        "pol=I,",
-        info,
-        "cmap:map=plasma",
+        &format!(
+            ":1:x:view=0.075:0.45,\
+            y:view=0.15:0.7,\
+            subint=I,\
+            pol=I,\
+            {info},\
+            cmap:map=plasma",
+        ),
         "-p",
         "time",
         "-c",
-        ":2:x:view=0.575:0.95,",
-        "y:view=0.15:0.7,",
-        "chan=I,",
-        "pol=I,",
-        "cmap:map=plasma",
+        ":2:x:view=0.575:0.95,\
+        y:view=0.15:0.7,\
+        chan=I,\
+        pol=I,\
+        cmap:map=plasma",
     ];
     _ = psrchive(config, "psrplot", &args)?;
 
@@ -114,23 +137,24 @@ fn plot_no_freq(
         "-p",
         "flux",
         "-c",
-        ":0:x:view=0.075:0.95,",
-        "y:view=0.5:0.7,",
-        "subint=I,",
-        "chan=I,",
-        "pol=I,",
-        "x:opt=BCTS,",
-        "x:lab=,",
-        "below:l=,",
-        info,
+        &format!(
+            ":0:x:view=0.075:0.95,\
+            y:view=0.5:0.7,\
+            subint=I,\
+            chan=I,\
+            pol=I,\
+            x:opt=BCTS,\
+            x:lab=,\
+            below:l=,{info}",
+        ),
         "-p",
         "time",
         "-c",
-        ":1:x:view=0.075:0.95,",
-        "y:view=0.15:0.5,",
-        "chan=I,",
-        "pol=I,",
-        "cmap:map=plasma",
+        ":1:x:view=0.075:0.95,\
+        y:view=0.15:0.5,\
+        chan=I,\
+        pol=I,\
+        cmap:map=plasma",
     ];
     _ = psrchive(config, "psrplot", &args)?;
 
@@ -198,13 +222,14 @@ fn plot_prof_only(
         "-p",
         "flux",
         "-c",
-        ":0:x:view=0.075:0.95,",
-        "y:view=0.15:0.7,",
-        "subint=I,",
-        "chan=I,",
-        "pol=I,",
-        "below:l=,",
-        info,
+        &format!(
+            ":0:x:view=0.075:0.95,\
+            y:view=0.15:0.7,\
+            subint=I,\
+            chan=I,\
+            pol=I,\
+            below:l=,{info}",
+        ),
     ];
     _ = psrchive(config, "psrplot", &args)?;
 
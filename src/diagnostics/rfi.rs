@@ -0,0 +1,52 @@
+//! Fraction of zapped channels, using `psrchive::psrstat`.
+use super::{Diagnostic, DiagnosticOut};
+use crate::{
+    ARPAError, Result, config::Config, conveniences::parse,
+    external_tools::psrchive,
+};
+use log::info;
+
+/// Finds the fraction of channels given zero weight, e.g. by `paz`, in the
+/// time-scrunched file.
+///
+/// # Errors
+/// Fails if `psrstat` fails or its output can't be parsed.
+pub fn run(config: &Config, path: &str) -> Result<DiagnosticOut> {
+    info!("Calculating RFI fraction for {path}...");
+    let res = psrchive(
+        config,
+        "psrstat",
+        &["-Qq", "-j", "T", "-l", "chan=0-", "-c", "int:wt", path],
+    )?;
+
+    let weights = res
+        .lines()
+        .map(|line| parse::<f32>(line.trim()))
+        .collect::<Result<Vec<_>>>()?;
+    if weights.is_empty() {
+        return Err(ARPAError::ParseFailed(res, "channel weights"));
+    }
+
+    let zapped = weights.iter().filter(|&&w| w == 0.0).count();
+    #[allow(clippy::cast_precision_loss)]
+    let fraction = zapped as f32 / weights.len() as f32;
+
+    Ok(DiagnosticOut::Value(fraction))
+}
+
+/// The `rfi_fraction` diagnostic.
+pub struct RfiFraction;
+impl Diagnostic for RfiFraction {
+    fn name(&self) -> &'static str {
+        "rfi_fraction"
+    }
+
+    fn run(
+        &self,
+        config: &Config,
+        file: &str,
+        _: &str,
+    ) -> Result<DiagnosticOut> {
+        run(config, file)
+    }
+}
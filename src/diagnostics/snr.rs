@@ -1,5 +1,5 @@
 //! Signal-to-noise ratio for fully scrunched data, using `psrchive::psrstat`.
-use super::DiagnosticOut;
+use super::{Diagnostic, DiagnosticOut};
 use crate::{
     Result, config::Config, conveniences::parse, external_tools::psrchive,
 };
@@ -12,3 +12,20 @@ pub fn run(config: &Config, path: &str) -> Result<DiagnosticOut> {
 
     Ok(DiagnosticOut::Value(parse(res.trim())?))
 }
+
+/// The `snr` diagnostic.
+pub struct Snr;
+impl Diagnostic for Snr {
+    fn name(&self) -> &'static str {
+        "snr"
+    }
+
+    fn run(
+        &self,
+        config: &Config,
+        file: &str,
+        _: &str,
+    ) -> Result<DiagnosticOut> {
+        run(config, file)
+    }
+}
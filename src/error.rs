@@ -1,4 +1,4 @@
-use std::{process::Output, string::FromUtf8Error};
+use std::{process::Output, string::FromUtf8Error, time::Duration};
 
 use crate::archivist::ArchivistError;
 
@@ -9,15 +9,25 @@ pub enum ARPAError {
     IOFault(std::io::Error),
     PSRUtils(psrutils::error::PsruError),
     ToolFailure(String, Output),
+    ToolTimeout(String, Duration),
     JoinThread(String),
     ConfigFailure(toml::de::Error),
+    InvalidConfig(String, String),
+    MissingEnvVar(String),
     MissingFileOrDirectory(String),
+    NotAFile(String),
+    NotADirectory(String),
     StringConversion(Vec<u8>),
     ArchivistError(ArchivistError),
 
     MalformedInput(String),
     ParseFailed(String, &'static str),
     ChecksumFail(String),
+    ArchiveCollision(String),
+    DuplicateContent(String, String, i32),
+    DuplicatePath(String, String, i32),
+    FileTooLarge(String, u64, u64),
+    UnexpectedObjectType(String, String),
 
     CantFind(String),
 
@@ -25,14 +35,28 @@ pub enum ARPAError {
     ChefNoTemplate,
     ChefNoRaw,
     MissingEphemeride(i32),
+    EphemerideMismatch(String, String),
+    DuplicateTOAs {
+        raw: i32,
+        template: i32,
+        method: String,
+        count: i64,
+    },
+    Cancelled,
+    WorkerStopped,
+    JobPanicked(u64, String),
     VapKeyCount(usize, usize),
 
     UnknownDiagnostic(String),
+    DiagnosticPanicked(String, String),
     DiagnosticPlotBadFile(String),
+    DiagnosticSingleChannel(String, String),
     TOAExpectedFormat(String),
+    MissingTOAPlot(String),
 }
 
 impl std::fmt::Display for ARPAError {
+    #[allow(clippy::too_many_lines)]
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::TokioJoinError(error) => write!(f, "[tokio] {error}",),
@@ -49,6 +73,11 @@ impl std::fmt::Display for ARPAError {
                 String::from_utf8_lossy(&out.stdout),
                 String::from_utf8_lossy(&out.stderr),
             ),
+            Self::ToolTimeout(tool, duration) => write!(
+                f,
+                "Tool \"{tool}\" was killed after running for {} s.",
+                duration.as_secs()
+            ),
             Self::JoinThread(msg) => write!(
                 f,
                 "One of your threads was unable to join, saying: \"{msg}\"",
@@ -56,9 +85,23 @@ impl std::fmt::Display for ARPAError {
             Self::ConfigFailure(err) => {
                 write!(f, "Encountered error reading config file: {err}",)
             }
+            Self::InvalidConfig(field, problem) => {
+                write!(f, "Invalid config value for `{field}`: {problem}")
+            }
+            Self::MissingEnvVar(name) => write!(
+                f,
+                "The config refers to the environment variable \"{name}\", \
+                which is not set."
+            ),
             Self::MissingFileOrDirectory(path) => {
                 write!(f, "File or directory \"{path}\" is missing.",)
             }
+            Self::NotAFile(path) => {
+                write!(f, "\"{path}\" exists, but is not a file.")
+            }
+            Self::NotADirectory(path) => {
+                write!(f, "\"{path}\" exists, but is not a directory.")
+            }
             Self::StringConversion(bytes) => {
                 write!(f, "Failed to parse string from bytes: {bytes:?}",)
             }
@@ -75,6 +118,29 @@ impl std::fmt::Display for ARPAError {
             Self::ChecksumFail(file) => {
                 write!(f, "Checksum falied for file \"{file}\".",)
             }
+            Self::ArchiveCollision(path) => {
+                write!(f, "A different file is already archived at \"{path}\".")
+            }
+            Self::DuplicateContent(path, table, id) => write!(
+                f,
+                "\"{path}\" has the same content as entry {id} in {table}; \
+                reuse that one instead."
+            ),
+            Self::DuplicatePath(path, table, id) => write!(
+                f,
+                "\"{path}\" is already registered as entry {id} in {table}, \
+                but with different content."
+            ),
+            Self::FileTooLarge(file, size, max) => write!(
+                f,
+                "File \"{file}\" is {size} bytes, but at most {max} are \
+                allowed.",
+            ),
+            Self::UnexpectedObjectType(file, object_type) => write!(
+                f,
+                "File \"{file}\" is of an object of type \"{object_type}\", \
+                which is not allowed."
+            ),
 
             Self::CantFind(thing) => write!(f, "Could not find {thing}.",),
 
@@ -92,6 +158,27 @@ impl std::fmt::Display for ARPAError {
                 "Pulsar with id {id} has no master parfile set, but it was \
                 required by the pipeline."
             ),
+            Self::DuplicateTOAs {
+                raw,
+                template,
+                method,
+                count,
+            } => write!(
+                f,
+                "There are already {count} TOA(s) from raw file {raw} with \
+                template {template} using {method}, and we're set to refuse \
+                duplicates.",
+            ),
+            Self::Cancelled => {
+                write!(f, "The pipeline was cancelled.")
+            }
+            Self::WorkerStopped => write!(f, "The worker has stopped."),
+            Self::JobPanicked(id, message) => {
+                write!(f, "Job {id} panicked: {message}")
+            }
+            Self::EphemerideMismatch(path, details) => {
+                write!(f, "Ephemeride \"{path}\" does not fit: {details}.")
+            }
             Self::VapKeyCount(keys, values) => write!(
                 f,
                 "Psrchive::vap was asked for {keys} values but returned \
@@ -101,13 +188,39 @@ impl std::fmt::Display for ARPAError {
             Self::UnknownDiagnostic(dia) => {
                 write!(f, "\"{dia}\" is not a recognised diagnostic tool.",)
             }
+            Self::DiagnosticPanicked(dia, message) => {
+                write!(f, "Diagnostic \"{dia}\" panicked: {message}")
+            }
             Self::DiagnosticPlotBadFile(file) => {
                 write!(f, "Can't figure out what you want to plot from {file}.",)
             }
+            Self::DiagnosticSingleChannel(dia, file) => write!(
+                f,
+                "Diagnostic \"{dia}\" needs several channels, but {file} has \
+                only one."
+            ),
             Self::TOAExpectedFormat(line) => write!(
                 f,
                 "Expected \"FORMAT 1\" from psrchive::pat, but got \"{line}\".",
             ),
+            Self::MissingTOAPlot(path) => write!(
+                f,
+                "psrchive::pat made no plot at \"{path}\"; is its plot \
+                device available?",
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ARPAError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::TokioJoinError(error) => Some(error),
+            Self::IOFault(error) => Some(error),
+            Self::PSRUtils(error) => Some(error),
+            Self::ConfigFailure(error) => Some(error),
+            Self::ArchivistError(error) => Some(error),
+            _ => None,
         }
     }
 }
@@ -1,14 +1,56 @@
 //! Functions to call external tools.
 
-use std::{ffi::OsStr, process::Command};
+use std::{
+    ffi::OsStr,
+    io::Read,
+    process::{Command, Output, Stdio},
+    time::{Duration, Instant},
+};
 
-use crate::{Result, config::Config};
-use log::{debug, info, warn};
+use crate::{ARPAError, Result, config::Config};
+use log::{debug, warn};
 
 /// Runs a psrchive tool `tool`, and returns its result.
+///
+/// The arguments are passed to the tool as they are, without a shell, so
+/// they need no quoting, e.g. for paths with spaces. Tokens like `$nbin` in
+/// `psrplot` labels are expanded by the tool itself, not by a shell.
+/// ```
+/// # use argos_arpa::{config::Config, external_tools::psrchive};
+/// # let dir = std::env::temp_dir().join("arpa psrchive doctest");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// # let config_path = dir.join("config.toml");
+/// # std::fs::write(&config_path, r#"
+/// # [database]
+/// # url = ""
+/// # pool_connections = 1
+/// # connection_timeout = 1
+/// # [behaviour]
+/// # archive_rawfiles = false
+/// # move_rawfiles = false
+/// # auto_add_pulsars = false
+/// # auto_resolve_duplicate_uploads = false
+/// # toa_fitting = "PGS"
+/// # diagnostics = []
+/// # [paths]
+/// # psrchive = "/bin"
+/// # rawfile_storage = ""
+/// # temp_dir = "/tmp"
+/// # diagnostics_dir = ""
+/// # "#).unwrap();
+/// # let config = Config::load(&config_path).unwrap();
+/// // Any tool in `paths.psrchive` can be run, here `/bin/cat`
+/// let path = dir.join("a file with spaces; and more.txt");
+/// std::fs::write(&path, "ok").unwrap();
+///
+/// assert_eq!(psrchive(&config, "cat", &[&path]).unwrap(), "ok");
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+///
 /// # Errors
 /// Fails if the tool cannot be called, if the tool fails, or if the tool's
-/// output is not UTF-8.
+/// output is not UTF-8. The tool fails if it exits unsuccessfully, or, with
+/// `behaviour.tool_stderr_is_error`, if it prints anything to stderr.
 pub fn psrchive(
     config: &Config,
     tool: &str,
@@ -22,60 +64,109 @@ pub fn psrchive(
             + &a.as_ref().to_string_lossy()),
     );
 
-    let tool_path = if config.paths.psrchive.is_empty() {
-        tool.to_string()
-    } else {
-        format!("{}/{}", config.paths.psrchive, tool)
-    };
+    let tool_path = psrchive_path(config, tool);
 
-    let t0 = std::time::Instant::now();
-    // let output = Command::new(tool_path).args(args).output()?;
-    let output = Command::new("/bin/sh")
-        .arg("-c")
-        .arg(args.iter().fold(tool_path, |acc, a| {
-            acc + " " + &a.as_ref().to_string_lossy()
-        }))
-        .output()?;
+    let t0 = Instant::now();
+    let mut command = Command::new(tool_path);
+    command.args(args);
+    let output = match config.behaviour.tool_timeout_secs {
+        0 => command.output()?,
+        secs => output_within(
+            &mut command,
+            &format!("psrchive::{tool}"),
+            Duration::from_secs(secs),
+        )?,
+    };
     debug!(
         "psrchive::{tool} finished in {} ms",
         t0.elapsed().as_millis()
     );
 
-    // if !output.status. {
-    //     return Err(ARPAError::ToolFailure(
-
-    //     );
-    // }
+    debug!(
+        "status: {} \n-- stdout:\n{}\n-- stderr:\n{}",
+        output.status,
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr),
+    );
 
     if !output.stderr.is_empty() {
         warn!(
-            "Tool printed the following to stderr: \n{}",
+            "psrchive::{tool} printed the following to stderr: \n{}",
             String::from_utf8_lossy(&output.stderr)
         );
     }
 
-    debug!(
-        "status: {} \n-- stdout:\n{}\n-- stderr:\n{}",
-        output.status,
-        String::from_utf8_lossy(&output.stdout),
-        String::from_utf8_lossy(&output.stderr),
-    );
+    // Some tools print notices to stderr even when they succeed, so by
+    // default only the exit status counts.
+    let stderr_fails = config.behaviour.tool_stderr_is_error;
+    if !output.status.success() || (stderr_fails && !output.stderr.is_empty()) {
+        return Err(ARPAError::ToolFailure(
+            format!("psrchive::{tool}"),
+            output,
+        ));
+    }
 
     let result = String::from_utf8(output.stdout)?;
     Ok(result)
 }
 
-/// Calls `tempo2` to perform a fit.
-/// # Errors
-/// Fails if tempo fails.
-pub fn tempo2_fit(par_file: &str, tim_file: &str) -> Result<()> {
-    let result = Command::new("tempo2")
-        .arg("-f")
-        .arg(par_file)
-        .arg(tim_file)
-        .status()?;
+/// Runs `command` like [`Command::output`], but kills it if it runs for
+/// longer than `timeout`.
+fn output_within(
+    command: &mut Command,
+    name: &str,
+    timeout: Duration,
+) -> Result<Output> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // The pipes are drained as the tool runs, so it can't block on them
+    let drain = |pipe: Option<Box<dyn Read + Send>>| {
+        std::thread::spawn(move || {
+            let mut buffer = Vec::new();
+            if let Some(mut pipe) = pipe {
+                _ = pipe.read_to_end(&mut buffer);
+            }
+            buffer
+        })
+    };
+    let stdout = drain(child.stdout.take().map(|p| Box::new(p) as _));
+    let stderr = drain(child.stderr.take().map(|p| Box::new(p) as _));
 
-    info!("{result}");
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if start.elapsed() > timeout {
+            warn!("{name} timed out; killing it.");
+            child.kill()?;
+            child.wait()?;
+            return Err(ARPAError::ToolTimeout(name.to_string(), timeout));
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    };
 
-    Ok(())
+    let join = |handle: std::thread::JoinHandle<Vec<u8>>| {
+        handle
+            .join()
+            .map_err(|err| ARPAError::JoinThread(format!("{err:?}")))
+    };
+    Ok(Output {
+        status,
+        stdout: join(stdout)?,
+        stderr: join(stderr)?,
+    })
+}
+
+/// The path used to call the psrchive tool `tool`.
+pub(crate) fn psrchive_path(config: &Config, tool: &str) -> String {
+    if config.paths.psrchive.is_empty() {
+        tool.to_string()
+    } else {
+        format!("{}/{}", config.paths.psrchive, tool)
+    }
 }
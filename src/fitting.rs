@@ -0,0 +1,199 @@
+//! Timing fits with external fitting backends.
+//!
+//! Each backend implements [`Fitter`], taking a par and a tim file and
+//! returning the post-fit residuals. Use [`fitter`] to get the one selected
+//! in the config.
+
+use std::process::Command;
+
+use log::{debug, info, warn};
+
+use crate::{
+    ARPAError, Result,
+    config::{Config, FittingBackend},
+    conveniences::parse,
+};
+
+/// Every line of residuals printed by a backend starts with this, to tell
+/// them apart from any other output.
+const RESIDUAL_MARKER: &str = "RES";
+
+/// A single post-fit residual.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Residual {
+    /// The (barycentric) arrival time, in MJD.
+    pub mjd: f64,
+    /// The post-fit residual, in seconds.
+    pub residual: f64,
+    /// The TOA uncertainty, in microseconds.
+    pub uncertainty: f64,
+}
+
+/// An external program fitting a timing model to TOAs.
+pub trait Fitter {
+    /// The name of the backend, for logging.
+    fn name(&self) -> &'static str;
+
+    /// Fits `par_file` to the TOAs in `tim_file`.
+    ///
+    /// # Errors
+    /// Fails if the backend can't be called, exits unsuccessfully, or prints
+    /// residuals that can't be parsed.
+    fn fit(&self, par_file: &str, tim_file: &str) -> Result<Vec<Residual>>;
+}
+
+/// Fits with `tempo2`, using its `general2` plugin to print residuals.
+pub struct Tempo2Fitter {
+    executable: String,
+}
+impl Tempo2Fitter {
+    /// Uses `tempo2` from the directory in the config, or from the `PATH`.
+    pub fn new(config: &Config) -> Self {
+        let executable = if config.paths.tempo2.is_empty() {
+            "tempo2".to_string()
+        } else {
+            format!("{}/tempo2", config.paths.tempo2)
+        };
+
+        Self { executable }
+    }
+}
+impl Fitter for Tempo2Fitter {
+    fn name(&self) -> &'static str {
+        "tempo2"
+    }
+
+    fn fit(&self, par_file: &str, tim_file: &str) -> Result<Vec<Residual>> {
+        let format = format!("{RESIDUAL_MARKER} {{bat}} {{post}} {{err}}\\n");
+        let output = Command::new(&self.executable)
+            .args(["-output", "general2", "-s", &format, "-f"])
+            .arg(par_file)
+            .arg(tim_file)
+            .output()?;
+
+        parse_output(self.name(), output)
+    }
+}
+
+/// Fits with PINT, through a short python script.
+pub struct PintFitter {
+    python: String,
+}
+impl PintFitter {
+    /// The script run by python, with the par and tim files as arguments.
+    const SCRIPT: &'static str = "\
+import sys
+from pint.models import get_model_and_toas
+from pint.fitter import Fitter
+model, toas = get_model_and_toas(sys.argv[1], sys.argv[2])
+fitter = Fitter.auto(toas, model)
+fitter.fit_toas()
+for mjd, res, err in zip(
+    toas.get_mjds().value,
+    fitter.resids.time_resids.to_value('s'),
+    toas.get_errors().to_value('us'),
+):
+    print('RES', mjd, res, err)
+";
+
+    /// Uses the python interpreter from the config, or `python3`.
+    pub fn new(config: &Config) -> Self {
+        let python = if config.paths.python.is_empty() {
+            "python3".to_string()
+        } else {
+            config.paths.python.clone()
+        };
+
+        Self { python }
+    }
+}
+impl Fitter for PintFitter {
+    fn name(&self) -> &'static str {
+        "pint"
+    }
+
+    fn fit(&self, par_file: &str, tim_file: &str) -> Result<Vec<Residual>> {
+        let output = Command::new(&self.python)
+            .args(["-c", Self::SCRIPT])
+            .arg(par_file)
+            .arg(tim_file)
+            .output()?;
+
+        parse_output(self.name(), output)
+    }
+}
+
+/// The root mean square of the post-fit `residuals`, in seconds, or `None`
+/// if there are none.
+///
+/// E.g.
+/// ```
+/// # use argos_arpa::fitting::{Residual, rms};
+/// let residual = |residual| Residual { mjd: 0.0, residual, uncertainty: 1.0 };
+/// assert_eq!(rms(&[residual(3e-6), residual(-4e-6)]), Some(12.5f64.sqrt() * 1e-6));
+/// assert_eq!(rms(&[]), None);
+/// ```
+#[allow(clippy::cast_precision_loss)]
+pub fn rms(residuals: &[Residual]) -> Option<f64> {
+    if residuals.is_empty() {
+        return None;
+    }
+
+    let sum = residuals.iter().map(|r| r.residual.powi(2)).sum::<f64>();
+    Some((sum / residuals.len() as f64).sqrt())
+}
+
+/// The fitting backend selected in `config`.
+pub fn fitter(config: &Config) -> Box<dyn Fitter> {
+    match config.behaviour.fitting_backend {
+        FittingBackend::Tempo2 => Box::new(Tempo2Fitter::new(config)),
+        FittingBackend::Pint => Box::new(PintFitter::new(config)),
+    }
+}
+
+/// Checks the exit status of a backend and collects the residual lines of
+/// its stdout, i.e. those starting with `RESIDUAL_MARKER` followed by three
+/// numbers.
+fn parse_output(
+    backend: &str,
+    output: std::process::Output,
+) -> Result<Vec<Residual>> {
+    if !output.status.success() {
+        return Err(ARPAError::ToolFailure(backend.to_string(), output));
+    }
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !stderr.trim().is_empty() {
+        warn!("{backend} printed to stderr:\n{stderr}");
+    }
+
+    let stdout = String::from_utf8(output.stdout)?;
+    debug!("{backend} printed:\n{stdout}");
+
+    let residuals = stdout
+        .lines()
+        .filter_map(|line| line.strip_prefix(RESIDUAL_MARKER))
+        .map(|line| {
+            let values = line.split_whitespace().collect::<Vec<_>>();
+            let &[mjd, residual, uncertainty] = values.as_slice() else {
+                return Err(ARPAError::MalformedInput(format!(
+                    "{backend} residual line \"{line}\""
+                )));
+            };
+
+            Ok(Residual {
+                mjd: parse(mjd)?,
+                residual: parse(residual)?,
+                uncertainty: parse(uncertainty)?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    info!(
+        "{backend} fit gave {} residuals, with an RMS of {:.3} μs",
+        residuals.len(),
+        rms(&residuals).unwrap_or_default() * 1e6
+    );
+
+    Ok(residuals)
+}
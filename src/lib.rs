@@ -12,9 +12,16 @@ pub mod conveniences;
 pub mod diagnostics;
 mod error;
 pub mod external_tools;
+pub mod fitting;
 pub mod pipeline;
+pub mod worker;
 
-pub use archivist::{Archivist, data_types, table::Table, table::TableItem};
+pub use archivist::{
+    Archivist, AutoAddEvent, DiagnosticStats, ImportOutcome, ImportReport,
+    Mismatch, ReadinessCheck, ReadinessReport, SqlValue, ToaProvenance,
+    VerificationHandle, VerificationProgress, data_types, table::ChecksumItem,
+    table::FileItem, table::InsertBinds, table::Table, table::TableItem,
+};
 pub use error::ARPAError;
 
 pub(crate) type Result<T> = std::result::Result<T, ARPAError>;
@@ -6,37 +6,65 @@
 //! The `parse_input_` functions are helpers to parse text as either `id`s or
 //! paths and take the corresponding actions.
 
-use std::{process::Command, time::Instant};
+use std::{ops::ControlFlow, process::Command, sync::Arc, time::Instant};
 
 use crate::{
-    ARPAError, Archivist,
-    config::Config,
-    conveniences::{assert_exists, compute_checksum, parse},
+    ARPAError, Archivist, ChecksumItem, Table,
+    config::{Config, Preprocessing},
+    conveniences::{assert_dir_exists, compute_checksum, psrchive_stem},
     data_types::{
-        DiagnosticPlot, ParMeta, ProcessInfo, PulsarMeta, RawFileHeader,
-        RawMeta, TOAInfo, TemplateMeta,
+        Checksum, DiagnosticPlot, ObsSystem, ParMeta, ProcessInfo,
+        ProcessedMeta, PulsarMeta, RawFileHeader, RawMeta, TOAInfo,
+        TemplateMeta, archive_copy,
+    },
+    diagnostics::{
+        DiagnosticOut, DiagnosticRegistry, run_tool, store_diagnostic,
     },
-    diagnostics::run_diagnostic,
     external_tools::psrchive,
 };
-use log::{debug, error, warn};
+use futures_util::future::join_all;
+use log::{debug, error, info, warn};
 use psrutils::{error::PsruError, timfile::TOAInfo as TOA};
+use tokio_util::sync::CancellationToken;
 
 mod arguments;
+mod cook_options;
+mod cook_report;
+mod ephemeride_check;
 mod progress;
+mod work_dir;
 pub use arguments::{
     parse_input_ephemeride, parse_input_raw, parse_input_template,
 };
+pub use cook_options::CookOptions;
+pub use cook_report::{CookReport, DiagnosticOutcome};
+use ephemeride_check::check_ephemeride;
+pub use ephemeride_check::verify_ephemeride;
 pub use progress::Status;
+use work_dir::WorkDir;
+
+/// How many TOAs are archived per round trip.
+const TOA_BATCH_SIZE: usize = 1000;
 
 /// Runs the toa-generation pipeline.
 ///
-/// The `status_callback` is just for information on the progress of the
-/// pipeline, the minimal (informing) case would be `|s: Status| info!("{s}")`.
+/// The `status_callback` gets information on the progress of the pipeline,
+/// and may stop it by returning [`ControlFlow::Break`], which makes `cook`
+/// roll back and fail with [`ARPAError::Cancelled`]. The same goes for the
+/// [`CancellationToken`] in `options`, if any. Either way, [`Status::Cancelled`] is
+/// sent. The minimal (informing)
+/// case would be `|s: Status| { info!("{s}"); ControlFlow::Continue(()) }`.
 ///
 /// Any errors encountered will be sent via the callback before propagating to
 /// the caller of this method.
 ///
+/// The `options` pick whether to run diagnostics, and may override the
+/// preprocessing profile of the raw file's obs system.
+///
+/// With `behaviour.temp_dir_per_run`, each run works in a directory of its
+/// own, which is removed when it succeeds, and kept (and reported in
+/// [`Status::Error`]) when it fails.
+///
 /// # Notes
 /// While it is possible to create the different `meta`s without uploading them
 /// to the database, doing so might cause errors down the line. Things like
@@ -44,6 +72,8 @@ pub use progress::Status;
 /// they do not exists. This will cause the whole pipeline to fail and any
 /// previous actions to be rolled back.
 ///
+/// On success, a [`CookReport`] of everything made is returned.
+///
 /// # Errors
 /// There are many ways this can fail, e.g.:
 ///  - the `archivist` fails;
@@ -51,114 +81,398 @@ pub use progress::Status;
 ///  - the database information is out of date.
 ///
 /// It should not fail because of bad luck though :)
-pub async fn cook<F: Fn(Status) + Send + Sync>(
+pub async fn cook<F: Fn(Status) -> ControlFlow<()> + Send + Sync>(
     archivist: &mut Archivist,
     raw: RawMeta,
     ephemeride: Option<ParMeta>,
     template: TemplateMeta,
-    diagnostics: bool,
+    options: &CookOptions,
     status_callback: F,
-) -> Result<(), ARPAError> {
+) -> Result<CookReport, ARPAError> {
+    cook_inner(
+        archivist,
+        raw,
+        ephemeride,
+        &template,
+        false,
+        options,
+        status_callback,
+    )
+    .await
+}
+
+/// Same as [`cook`], but with a template that has already been verified. When
+/// cooking many files with the same template, this saves checksumming it for
+/// every file.
+///
+/// # Errors
+/// See [`cook`].
+pub async fn cook_verified<F: Fn(Status) -> ControlFlow<()> + Send + Sync>(
+    archivist: &mut Archivist,
+    raw: RawMeta,
+    ephemeride: Option<ParMeta>,
+    template: &VerifiedTemplate,
+    options: &CookOptions,
+    status_callback: F,
+) -> Result<CookReport, ARPAError> {
+    cook_inner(
+        archivist,
+        raw,
+        ephemeride,
+        &template.0,
+        true,
+        options,
+        status_callback,
+    )
+    .await
+}
+
+/// A template whose file has been checked against its checksum.
+#[derive(Debug, Clone)]
+pub struct VerifiedTemplate(TemplateMeta);
+impl VerifiedTemplate {
+    /// Checks the template's file against its checksum.
+    ///
+    /// # Errors
+    /// Fails if the file can't be read or its checksum doesn't match.
+    pub fn new(
+        config: &Config,
+        template: TemplateMeta,
+    ) -> Result<Self, ARPAError> {
+        verify_template(config, &template, |_| ControlFlow::Continue(()))?;
+        Ok(Self(template))
+    }
+
+    /// The verified template.
+    pub const fn template(&self) -> &TemplateMeta {
+        &self.0
+    }
+}
+
+async fn cook_inner<F: Fn(Status) -> ControlFlow<()> + Send + Sync>(
+    archivist: &mut Archivist,
+    raw: RawMeta,
+    ephemeride: Option<ParMeta>,
+    template: &TemplateMeta,
+    template_verified: bool,
+    options: &CookOptions,
+    status_callback: F,
+) -> Result<CookReport, ARPAError> {
     let start = Instant::now();
-    let pulsar_name = archivist
-        .get::<PulsarMeta>(raw.pulsar_id)
-        .await
-        .inspect_err(|e| status_callback(Status::Error(e.to_string())))?
-        .alias;
-
-    status_callback(Status::Starting {
-        raw: (raw.file_path.clone(), raw.id),
-        pulsar: (pulsar_name, raw.pulsar_id),
-        ephemeride: ephemeride.clone().map(|e| (e.file_path, e.id)),
-        template: template.id,
-    });
+    let work_dir =
+        WorkDir::new(archivist.config(), raw.id).inspect_err(|e| {
+            _ = status_callback(Status::Error(e.to_string(), None));
+        })?;
+
+    let result = cook_steps(
+        archivist,
+        raw,
+        ephemeride,
+        template,
+        template_verified,
+        options,
+        work_dir.path(),
+        &status_callback,
+    )
+    .await;
+
+    match result {
+        Ok(mut cook_report) => {
+            work_dir.clean_up();
+            cook_report.duration = start.elapsed();
+            _ = status_callback(Status::Finished(cook_report.duration));
+            Ok(cook_report)
+        }
+        Err(ARPAError::Cancelled) => {
+            work_dir.clean_up();
+            _ = status_callback(Status::Cancelled);
+            Err(ARPAError::Cancelled)
+        }
+        Err(err) => {
+            _ = status_callback(Status::Error(
+                err.to_string(),
+                work_dir.retain(),
+            ));
+            Err(err)
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments, clippy::too_many_lines)]
+async fn cook_steps<F: Fn(Status) -> ControlFlow<()> + Send + Sync>(
+    archivist: &mut Archivist,
+    raw: RawMeta,
+    ephemeride: Option<ParMeta>,
+    template: &TemplateMeta,
+    template_verified: bool,
+    options: &CookOptions,
+    work_dir: &str,
+    status_callback: F,
+) -> Result<CookReport, ARPAError> {
+    let diagnostics = options.diagnostics;
+    if diagnostics {
+        archivist
+            .config()
+            .behaviour
+            .check_diagnostics(archivist.diagnostics())?;
+    }
+    let pulsar_name = archivist.get::<PulsarMeta>(raw.pulsar_id).await?.alias;
+
+    report(
+        &status_callback,
+        Status::Starting {
+            raw: (raw.file_path.clone(), raw.id),
+            pulsar: (pulsar_name.clone(), raw.pulsar_id),
+            ephemeride: ephemeride.clone().map(|e| (e.file_path, e.id)),
+            template: template.id,
+        },
+    )?;
+
+    let profile = if let Some(profile) = options.preprocessing {
+        profile
+    } else {
+        let obs_system = archivist.get::<ObsSystem>(raw.observer_id).await?;
+        archivist.config().preprocessing_for(obs_system.name())
+    };
 
     let user_id = 0;
-    let new_path = format!("{}/working.ar", archivist.config().paths.temp_dir);
+    let new_path = format!("{work_dir}/working.ar");
+    let mut timings = Vec::new();
 
+    let cancel = options.cancel.as_ref();
+    check_cancelled(cancel)?;
+    let step = Instant::now();
     manipulate(
         archivist.config(),
         &raw,
         ephemeride.as_ref(),
         &new_path,
+        profile,
         &status_callback,
-    )
-    .inspect_err(|e| status_callback(Status::Error(e.to_string())))?;
+    )?;
+    timings.push(("manipulate", step.elapsed()));
 
+    if !template_verified {
+        let step = Instant::now();
+        verify_template(archivist.config(), template, &status_callback)?;
+        timings.push(("verify template", step.elapsed()));
+    }
+
+    check_cancelled(cancel)?;
+    let step = Instant::now();
     let toa_meta = generate_toas(
         archivist.config(),
-        &template,
+        template,
         &new_path,
+        work_dir,
         diagnostics,
         &status_callback,
-    )
-    .inspect_err(|e| status_callback(Status::Error(e.to_string())))?;
+    )?;
+    timings.push(("generate TOAs", step.elapsed()));
 
-    archivist
-        .start_transaction()
-        .await
-        .inspect_err(|e| status_callback(Status::Error(e.to_string())))?;
+    if options.dry_run {
+        let count = dry_run(
+            archivist,
+            &toa_meta,
+            &raw,
+            template,
+            &pulsar_name,
+            diagnostics,
+            &status_callback,
+        )
+        .await?;
+        return Ok(CookReport {
+            raw_id: raw.id,
+            dry_run_toas: Some(count),
+            timings,
+            ..Default::default()
+        });
+    }
 
-    let (process_id, toa_ids) = archive_toas(
-        archivist,
-        &toa_meta,
-        user_id,
-        &raw,
-        ephemeride.as_ref(),
-        &template,
-        &status_callback,
-    )
-    .await
-    .inspect_err(|e| status_callback(Status::Error(e.to_string())))?;
+    check_cancelled(cancel)?;
+    archivist.start_transaction().await?;
+
+    // The archived processed file, to remove if the run is rolled back
+    let mut stored_processed = None;
+    let archived = async {
+        let step = Instant::now();
+        let (process_id, toa_ids) = archive_toas(
+            &mut *archivist,
+            &toa_meta,
+            user_id,
+            &raw,
+            ephemeride.as_ref(),
+            template,
+            &status_callback,
+        )
+        .await?;
+        timings.push(("archive TOAs", step.elapsed()));
 
-    // > Create diagnostics & register plots ------------------------------
-    if diagnostics {
-        do_diagnostics(
-            archivist,
-            &new_path,
+        let mut cook_report = CookReport {
+            raw_id: raw.id,
             process_id,
-            toa_meta,
             toa_ids,
-            &status_callback,
-        )
-        .await
-        .inspect_err(|e| status_callback(Status::Error(e.to_string())))?;
+            ..Default::default()
+        };
+
+        // > Create diagnostics & register plots --------------------------
+        let processed_stem = toa_meta.stem.clone();
+        if diagnostics {
+            let step = Instant::now();
+            (cook_report.diagnostics, cook_report.plots) = do_diagnostics(
+                &mut *archivist,
+                &new_path,
+                work_dir,
+                &pulsar_name,
+                process_id,
+                toa_meta,
+                &cook_report.toa_ids,
+                cancel,
+                &status_callback,
+            )
+            .await?;
+            timings.push(("diagnostics", step.elapsed()));
+        }
+
+        // After the diagnostics, which still need the processed file
+        if archivist.config().behaviour.archive_processed {
+            let (id, path) = archive_processed(
+                &mut *archivist,
+                &new_path,
+                &pulsar_name,
+                &processed_stem,
+                process_id,
+                &status_callback,
+            )
+            .await?;
+            cook_report.processed_id = Some(id);
+            stored_processed = Some(path);
+        }
+
+        // Cancelling during the last step still throws it away
+        check_cancelled(cancel)?;
+        cook_report.timings = timings;
+        Ok::<_, ARPAError>(cook_report)
+    }
+    .await;
+
+    // Whether it failed or was cancelled, nothing of this run should stay
+    let result = match archived {
+        Ok(cook_report) => archivist
+            .commit_transaction()
+            .await
+            .map(|()| cook_report)
+            .map_err(ARPAError::from),
+        Err(err) => {
+            if let Err(rollback_err) = archivist.rollback_transaction().await {
+                error!("Could not roll back: {rollback_err}");
+            }
+            Err(err)
+        }
+    };
+    if result.is_err()
+        && let Some(path) = stored_processed
+    {
+        remove_stored(&path);
+    }
+
+    result
+}
+
+/// Removes a file archived by a run that was rolled back, so no file is left
+/// without a row.
+fn remove_stored(path: &str) {
+    match std::fs::remove_file(path) {
+        Ok(()) => info!("Removed {path}, as its run was rolled back."),
+        Err(err) => error!("Could not remove {path}: {err}"),
+    }
+}
+
+/// Sends `status` to the callback, failing if it asks to stop.
+fn report<F: Fn(Status) -> ControlFlow<()>>(
+    status_callback: &F,
+    status: Status,
+) -> Result<(), ARPAError> {
+    if status_callback(status).is_break() {
+        return Err(ARPAError::Cancelled);
+    }
+
+    Ok(())
+}
+
+/// Fails if `cancel` has been cancelled.
+fn check_cancelled(
+    cancel: Option<&CancellationToken>,
+) -> Result<(), ARPAError> {
+    if cancel.is_some_and(CancellationToken::is_cancelled) {
+        return Err(ARPAError::Cancelled);
     }
-    archivist
-        .commit_transaction()
-        .await
-        .inspect_err(|e| status_callback(Status::Error(e.to_string())))?;
 
-    status_callback(Status::Finished(start.elapsed()));
     Ok(())
 }
 
+/// Generates TOAs from an already prepared archive at `path`, without the
+/// rest of the pipeline. Nothing is written to the database, and no plots are
+/// made.
+///
+/// This is useful if you preprocess your files in your own way, and when
+/// testing.
+///
+/// # Errors
+/// Fails if the template does not match its checksum, if `psrchive` fails, or
+/// if its output can't be parsed.
+pub fn generate_toas_from_file(
+    config: &Config,
+    template: &TemplateMeta,
+    path: &str,
+) -> Result<Vec<TOA>, ARPAError> {
+    verify_template(config, template, |_| ControlFlow::Continue(()))?;
+    let toa_meta = generate_toas(
+        config,
+        template,
+        path,
+        &config.paths.temp_dir,
+        false,
+        |_| ControlFlow::Continue(()),
+    )?;
+
+    let toas = toa_meta
+        .lines()
+        .map(TOA::from_line_tempo2)
+        .collect::<Result<Vec<_>, PsruError>>()?;
+
+    Ok(toas)
+}
+
 struct TOAMeta {
-    toas: Vec<String>,
-    name: String,
+    /// The output of `psrchive::pat`, one TOA per line after the format line.
+    output: String,
+    /// The psrchive-style file stem, e.g. `J0437-4715_60000_43200`.
+    stem: String,
     channels: i16,
     subints: i16,
-    intmjd: u16,
-    secs: u32,
+    plotted: bool,
 }
 
-fn manipulate<F: Fn(Status)>(
+fn manipulate<F: Fn(Status) -> ControlFlow<()>>(
     config: &Config,
     raw: &RawMeta,
     ephemeride: Option<&ParMeta>,
     adjust_path: &str,
+    profile: Preprocessing,
     status_callback: F,
 ) -> Result<(), ARPAError> {
     // Make a new file for adjusting
-    status_callback(Status::Copying(
-        raw.file_path.clone(),
-        adjust_path.to_string(),
-    ));
+    report(
+        &status_callback,
+        Status::Copying(raw.file_path.clone(), adjust_path.to_string()),
+    )?;
     std::fs::copy(&raw.file_path, adjust_path)?;
 
     // > If parfile: reinstall ephemerides with pam -----------------------
     if let Some(par) = ephemeride {
-        status_callback(Status::InstallingEphemeride);
+        check_ephemeride(config, par, &raw.file_path)?;
+        report(&status_callback, Status::InstallingEphemeride)?;
         // Threre's no output...
         _ = psrchive(
             config,
@@ -168,10 +482,18 @@ fn manipulate<F: Fn(Status)>(
     }
 
     // Make a new file for manipulating
-    manipulate_pam(config, adjust_path, 1, 4, None, None, status_callback)
+    manipulate_pam(
+        config,
+        adjust_path,
+        profile.n_subints,
+        profile.n_channels,
+        profile.n_bins,
+        profile.t_subints,
+        status_callback,
+    )
 }
 
-fn manipulate_pam<F: Fn(Status)>(
+fn manipulate_pam<F: Fn(Status) -> ControlFlow<()>>(
     config: &Config,
     in_path: &str,
     n_subints: usize,
@@ -183,7 +505,7 @@ fn manipulate_pam<F: Fn(Status)>(
     // We need to copy in->out. pam will just say "no filenames were specified"
     // if a file is specified, but doesn't exist. I guess it works in-place
     // std::fs::copy(in_path, out_path)?;
-    status_callback(Status::Manipulating);
+    report(&status_callback, Status::Manipulating)?;
 
     let mut args = vec![
         "-m".to_string(),
@@ -207,24 +529,41 @@ fn manipulate_pam<F: Fn(Status)>(
     Ok(())
 }
 
-#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
-fn generate_toas<F: Fn(Status)>(
+fn verify_template<F: Fn(Status) -> ControlFlow<()>>(
     config: &Config,
     template: &TemplateMeta,
-    manip_path: &str,
-    plot: bool,
     status_callback: F,
-) -> Result<TOAMeta, ARPAError> {
-    status_callback(Status::VerifyingTemplate);
+) -> Result<(), ARPAError> {
+    report(&status_callback, Status::VerifyingTemplate)?;
 
     // Double check cheksum
-    let checksum = compute_checksum(&template.file_path, true)?;
-    if checksum != template.checksum.as_u128() {
+    let checksum = Checksum::from(compute_checksum(
+        &template.file_path,
+        config.behaviour.checksum_algorithm,
+        config.behaviour.progress.reporter().as_mut(),
+    )?);
+    if checksum != template.checksum() {
         return Err(ARPAError::ChecksumFail(template.file_path.clone()));
     }
 
-    status_callback(Status::GeneratingTOAs);
-    let plot_file = format!("{}/toa_diag.png/PNG", config.paths.temp_dir);
+    Ok(())
+}
+
+fn generate_toas<F: Fn(Status) -> ControlFlow<()>>(
+    config: &Config,
+    template: &TemplateMeta,
+    manip_path: &str,
+    work_dir: &str,
+    plot: bool,
+    status_callback: F,
+) -> Result<TOAMeta, ARPAError> {
+    report(&status_callback, Status::GeneratingTOAs)?;
+    let plot_path = format!("{work_dir}/toa_diag.png");
+    let plot_file = format!("{plot_path}/PNG");
+    if plot {
+        // A plot left over from a previous run would hide a failing device
+        _ = std::fs::remove_file(&plot_path);
+    }
     let mut args = vec![
         "-f",
         "tempo2",
@@ -233,7 +572,7 @@ fn generate_toas<F: Fn(Status)>(
         "-s",
         &template.file_path,
         "-C",
-        "gof length bw nbin nchan nsubint",
+        "gof length bw nbin nchan nsubint subint chan",
     ];
 
     if plot {
@@ -251,6 +590,17 @@ fn generate_toas<F: Fn(Status)>(
     }
     debug!("Got toas!");
 
+    // Without a working plot device pat just doesn't make any plots, so we
+    // check here rather than when moving them
+    let plotted = plot && std::fs::exists(&plot_path)?;
+    if plot && !plotted {
+        if config.behaviour.require_toa_plots {
+            return Err(ARPAError::MissingTOAPlot(plot_path));
+        }
+        warn!("psrchive::pat made no plot at {plot_path}; skipping plots.");
+        report(&status_callback, Status::SkippingTOAPlots)?;
+    }
+
     // Now pat has modified the manip file, so we can read from it
     let header = RawFileHeader::get_items(
         config,
@@ -259,24 +609,31 @@ fn generate_toas<F: Fn(Status)>(
     )?;
     debug!("Got header!");
 
-    let secs = (parse::<f32>(&header[4])? * 24. * 3600.).round() as u32;
-    let mut toas: Vec<String> =
-        result.lines().map(ToString::to_string).collect();
-    toas.remove(0); // The format specifier
-
-    status_callback(Status::GotTOAs(toas.len()));
-
-    Ok(TOAMeta {
-        toas,
-        name: header[3].clone(),
-        channels: parse(&header[1])?,
-        subints: parse(&header[2])?,
-        intmjd: parse(&header[4])?,
-        secs,
-    })
+    let stem = psrchive_stem(
+        &header.parse::<String>("name")?,
+        header.parse("intmjd")?,
+        header.parse("fracmjd")?,
+    )?;
+    let toa_meta = TOAMeta {
+        output: result,
+        stem,
+        channels: header.parse("nchan")?,
+        subints: header.parse("nsub")?,
+        plotted,
+    };
+    report(&status_callback, Status::GotTOAs(toa_meta.lines().count()))?;
+
+    Ok(toa_meta)
 }
 
-async fn archive_toas<F: Fn(Status)>(
+impl TOAMeta {
+    /// The TOA lines, skipping the format specifier.
+    fn lines(&self) -> impl Iterator<Item = &str> {
+        self.output.lines().skip(1)
+    }
+}
+
+async fn archive_toas<F: Fn(Status) -> ControlFlow<()>>(
     archivist: &mut Archivist,
     toa_meta: &TOAMeta,
     user_id: i32,
@@ -285,7 +642,11 @@ async fn archive_toas<F: Fn(Status)>(
     template: &TemplateMeta,
     status_callback: F,
 ) -> Result<(i32, Vec<i32>), ARPAError> {
-    status_callback(Status::LoggingProcess);
+    if archivist.config().behaviour.refuse_duplicate_toas {
+        refuse_duplicate_toas(archivist, raw, template).await?;
+    }
+
+    report(&status_callback, Status::LoggingProcess)?;
     let meta = ProcessInfo::new(
         user_id,
         raw,
@@ -298,95 +659,289 @@ async fn archive_toas<F: Fn(Status)>(
     let process_id = archivist.insert(meta).await?;
 
     // > Parse the output of psrchive::pat and insert toas ----------------
-    status_callback(Status::ParsingTOAs);
-    let toas = toa_meta
-        .toas
-        .iter()
-        .map(|l| {
-            TOA::from_line_tempo2(l).map(|toa| {
-                TOAInfo::extract(
-                    &toa,
-                    raw.pulsar_id,
-                    raw.observer_id,
-                    process_id,
-                    template.id,
-                    raw.id,
-                )
-            })
-        })
-        .collect::<Result<Vec<_>, PsruError>>()?;
-
-    let mut ids = Vec::with_capacity(toas.len());
-    for toa in toas {
-        ids.push(archivist.insert(toa).await?);
+    // The lines are parsed and inserted in batches, so only the output is
+    // ever held in full, however many TOAs there are.
+    report(&status_callback, Status::ParsingTOAs)?;
+    let mut ids = Vec::new();
+    let mut batch = Vec::with_capacity(TOA_BATCH_SIZE);
+    for line in toa_meta.lines() {
+        batch.push(TOAInfo::extract(
+            &TOA::from_line_tempo2(line)?,
+            raw.pulsar_id,
+            raw.observer_id,
+            process_id,
+            template.id,
+            raw.id,
+        ));
+        if batch.len() == TOA_BATCH_SIZE {
+            ids.append(&mut archivist.insert_many(batch).await?);
+            batch = Vec::with_capacity(TOA_BATCH_SIZE);
+        }
     }
-    status_callback(Status::ArchivedTOAs(ids.len()));
+    ids.append(&mut archivist.insert_many(batch).await?);
+    report(&status_callback, Status::ArchivedTOAs(ids.len()))?;
 
     Ok((process_id, ids))
 }
 
-async fn do_diagnostics<F: Fn(Status)>(
+/// Goes through what [`archive_toas`], [`do_diagnostics`], and
+/// [`archive_processed`] would do, logging it instead of writing anything.
+/// The TOAs are still parsed, so bad output is caught. Returns how many TOAs
+/// would have been archived.
+async fn dry_run<F: Fn(Status) -> ControlFlow<()>>(
+    archivist: &Archivist,
+    toa_meta: &TOAMeta,
+    raw: &RawMeta,
+    template: &TemplateMeta,
+    pulsar_name: &str,
+    diagnostics: bool,
+    status_callback: F,
+) -> Result<usize, ARPAError> {
+    let behaviour = &archivist.config().behaviour;
+    if behaviour.refuse_duplicate_toas {
+        refuse_duplicate_toas(archivist, raw, template).await?;
+    }
+
+    report(&status_callback, Status::ParsingTOAs)?;
+    let mut count = 0;
+    for line in toa_meta.lines() {
+        TOA::from_line_tempo2(line)?;
+        count += 1;
+    }
+    info!(
+        "Dry run: would log a process ({} channels, {} subints, method {}) \
+        and archive {count} TOA(s).",
+        toa_meta.channels, toa_meta.subints, behaviour.toa_fitting
+    );
+
+    if diagnostics {
+        info!(
+            "Dry run: would run diagnostics {:?} and archive {} TOA plot(s).",
+            behaviour.diagnostics_for_pulsar(pulsar_name),
+            if toa_meta.plotted { count } else { 0 }
+        );
+    }
+    if behaviour.archive_processed {
+        info!("Dry run: would archive the processed file.");
+    }
+
+    report(&status_callback, Status::DryRun(count))?;
+    Ok(count)
+}
+
+/// Archives a copy of the processed file at `path` under
+/// `{rawfile_storage}/processed/{PSR}/`, and logs it as made by `process_id`.
+/// Returns the id of the log and the path of the copy.
+async fn archive_processed<F: Fn(Status) -> ControlFlow<()>>(
     archivist: &mut Archivist,
-    adjust_path: &str,
+    path: &str,
+    pulsar_name: &str,
+    stem: &str,
     process_id: i32,
-    toa_meta: TOAMeta,
-    toa_ids: Vec<i32>,
     status_callback: F,
+) -> Result<(i32, String), ARPAError> {
+    let directory = format!(
+        "{}/processed/{}",
+        archivist.config().paths.rawfile_storage,
+        pulsar_name.to_uppercase()
+    );
+    let mut file_path = path.to_string();
+    let checksum = archive_copy(
+        archivist.config(),
+        &mut file_path,
+        &directory,
+        &format!("{stem}.{process_id}.ar"),
+    )
+    .await?;
+
+    // A copy without a row would be left behind for good
+    let id = match archivist
+        .insert(ProcessedMeta::new(process_id, checksum, file_path.clone()))
+        .await
+    {
+        Ok(id) => id,
+        Err(err) => {
+            remove_stored(&file_path);
+            return Err(err.into());
+        }
+    };
+    if let Err(err) = report(
+        &status_callback,
+        Status::ArchivedProcessed(file_path.clone()),
+    ) {
+        remove_stored(&file_path);
+        return Err(err);
+    }
+
+    Ok((id, file_path))
+}
+
+/// Fails if there already are TOAs from `raw` and `template`, made with the
+/// currently configured method.
+async fn refuse_duplicate_toas(
+    archivist: &Archivist,
+    raw: &RawMeta,
+    template: &TemplateMeta,
 ) -> Result<(), ARPAError> {
-    status_callback(Status::Diagnosing(
-        archivist.config().behaviour.diagnostics.len(),
-    ));
+    let method = &archivist.config().behaviour.toa_fitting;
+    let (count,): (i64,) = archivist
+        .get_special(
+            Table::Toas,
+            "count(*)",
+            &format!(
+                "rawfile_id={} and template_id={} and process_id in \
+                (select id from process_meta where method='{method}')",
+                raw.id, template.id,
+            ),
+        )
+        .await?
+        .unwrap_or((0,));
+
+    if count > 0 {
+        return Err(ARPAError::DuplicateTOAs {
+            raw: raw.id,
+            template: template.id,
+            method: method.clone(),
+            count,
+        });
+    }
 
-    let header = RawFileHeader::get(archivist.config(), adjust_path)?;
+    Ok(())
+}
+
+/// Runs the tools of all `diagnostics` on `file` at once, each on a blocking
+/// task of its own, and returns their outputs in the same order.
+async fn compute_diagnostics(
+    config: Arc<Config>,
+    registry: &DiagnosticRegistry,
+    diagnostics: &[String],
+    file: &str,
+    work_dir: &str,
+) -> Vec<Result<DiagnosticOut, ARPAError>> {
+    let tasks = diagnostics.iter().map(|diagnostic| {
+        let tool = registry.get_shared(diagnostic);
+        let name = diagnostic.clone();
+        let config = Arc::clone(&config);
+        let file = file.to_string();
+        let work_dir = work_dir.to_string();
+        tokio::task::spawn_blocking(move || {
+            tool.map_or_else(
+                || Err(ARPAError::UnknownDiagnostic(name)),
+                |tool| run_tool(&config, tool.as_ref(), &file, &work_dir),
+            )
+        })
+    });
+
+    // `run_tool` catches panics, so joining only fails on a cancelled task
+    join_all(tasks)
+        .await
+        .into_iter()
+        .map(|joined| {
+            joined.map_err(|err| ARPAError::JoinThread(err.to_string()))?
+        })
+        .collect()
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn do_diagnostics<F: Fn(Status) -> ControlFlow<()>>(
+    archivist: &mut Archivist,
+    adjust_path: &str,
+    work_dir: &str,
+    pulsar_name: &str,
+    process_id: i32,
+    toa_meta: TOAMeta,
+    toa_ids: &[i32],
+    cancel: Option<&CancellationToken>,
+    status_callback: F,
+) -> Result<(Vec<DiagnosticOutcome>, Vec<String>), ARPAError> {
+    let diagnostics = archivist
+        .config()
+        .behaviour
+        .diagnostics_for_pulsar(pulsar_name)
+        .to_vec();
+    report(&status_callback, Status::Diagnosing(diagnostics.len()))?;
+
+    assert_dir_exists(&archivist.config().paths.diagnostics_dir)?;
+    let header = RawFileHeader::get_cached(archivist.config(), adjust_path)?;
     let dir = header.get_intended_directory(archivist.config());
 
     // We put the diagnostic together with the rawfile
     let diag_path = format!("{dir}/process{process_id}");
-    // And add a symlink at the top
-    let crossref_path = format!(
-        "{}/process{}",
-        archivist.config().paths.diagnostics_dir,
-        process_id,
-    );
+    // And add a symlink in the diagnostics tree
+    let crossref_dir = header.get_crossref_directory(archivist.config());
+    std::fs::create_dir_all(&crossref_dir)?;
+    let crossref_path = format!("{crossref_dir}/process{process_id}");
     _ = Command::new("ln")
         .args(["-s", &diag_path, &crossref_path])
         .output()?;
 
-    let diagnostics = archivist.config().behaviour.diagnostics.clone();
-    for diagnostic in diagnostics {
-        let status = run_diagnostic(
-            archivist,
-            &diagnostic,
-            process_id,
-            adjust_path,
-            &diag_path,
-        )
-        .await;
+    // The tools are independent of each other, so they run at once, and
+    // only storing their results goes through the archivist in turn
+    check_cancelled(cancel)?;
+    let outs = compute_diagnostics(
+        archivist.shared_config(),
+        archivist.diagnostics(),
+        &diagnostics,
+        adjust_path,
+        work_dir,
+    )
+    .await;
+
+    let mut outcomes = Vec::with_capacity(diagnostics.len());
+    let mut plots = Vec::new();
+    for (diagnostic, out) in diagnostics.into_iter().zip(outs) {
+        check_cancelled(cancel)?;
+        // A failing diagnostic should not take the TOAs down with it
+        archivist.savepoint("diagnostic").await?;
+        let status = match out {
+            Ok(out) => {
+                store_diagnostic(
+                    archivist,
+                    &diagnostic,
+                    process_id,
+                    out,
+                    &diag_path,
+                )
+                .await
+            }
+            Err(err) => Err(err),
+        };
+        if status.is_ok() {
+            archivist.release_savepoint("diagnostic").await?;
+        } else {
+            archivist.rollback_to_savepoint("diagnostic").await?;
+        }
 
-        status_callback(Status::FinishedDiagnostic {
-            diagnostic,
-            passed: status.is_ok(),
-        });
+        report(
+            &status_callback,
+            Status::FinishedDiagnostic {
+                diagnostic: diagnostic.clone(),
+                passed: status.is_ok(),
+            },
+        )?;
 
-        if let Err(err) = status {
-            error!("{err}\n\nContinuing anyway...");
-        }
+        let error = match status {
+            Ok(plot) => {
+                plots.extend(plot);
+                None
+            }
+            Err(err) => {
+                error!("{err}\n\nContinuing anyway...");
+                Some(err.to_string())
+            }
+        };
+        outcomes.push(DiagnosticOutcome { diagnostic, error });
     }
 
     // Move toa diagplot too
-    let toa_diag_path =
-        &format!("{}/toa_diag.png", archivist.config().paths.temp_dir,);
+    let toa_diag_path = &format!("{work_dir}/toa_diag.png");
 
-    if assert_exists(toa_diag_path).is_err() {
-        warn!("TOA diagnostic plot not found.");
-        status_callback(Status::ArchivedTOAPlots(None));
-        return Ok(());
+    if !toa_meta.plotted {
+        report(&status_callback, Status::ArchivedTOAPlots(None))?;
+        return Ok((outcomes, plots));
     }
 
-    let base_path = format!(
-        "{}/{}_{:05}_{:05}",
-        diag_path, toa_meta.name, toa_meta.intmjd, toa_meta.secs,
-    );
+    let base_path = format!("{diag_path}/{}", toa_meta.stem);
     for (i, id) in toa_ids.iter().enumerate() {
         let dst = format!("{base_path}.TOA{id}.png");
         let src = if i == 0 {
@@ -396,6 +951,7 @@ async fn do_diagnostics<F: Fn(Status)>(
         };
 
         std::fs::rename(&src, &dst)?;
+        plots.push(dst.clone());
         let meta = DiagnosticPlot {
             id: 0,
             process: process_id,
@@ -405,6 +961,9 @@ async fn do_diagnostics<F: Fn(Status)>(
         archivist.insert(meta).await?;
     }
 
-    status_callback(Status::ArchivedTOAPlots(Some(toa_ids.len())));
-    Ok(())
+    report(
+        &status_callback,
+        Status::ArchivedTOAPlots(Some(toa_ids.len())),
+    )?;
+    Ok((outcomes, plots))
 }
@@ -2,8 +2,14 @@ use log::{debug, info, warn};
 
 use crate::{
     ARPAError, Archivist,
-    conveniences::assert_exists,
-    data_types::{ParMeta, RawMeta, TemplateMeta},
+    archivist::table::ChecksumItem,
+    conveniences::{
+        NoProgress, assert_dir_exists, assert_file_exists, compute_checksum,
+    },
+    data_types::{
+        Collision, ParMeta, PulsarMeta, RawMeta, TemplateMeta, archive_file,
+        preview_archive_file,
+    },
 };
 
 /// Parses `text` to load a `RawMeta`. This will try two things:
@@ -66,33 +72,29 @@ async fn ephermeride_from_file(
     path: &str,
 ) -> Result<ParMeta, ARPAError> {
     debug!("Parsing ephemeride path");
-    assert_exists(path)?;
+    assert_file_exists(path)?;
+
+    let path = if archivist.config().behaviour.archive_ephemerides {
+        archive_upload(archivist, raw.pulsar_id, path, "ephemerides").await?
+    } else {
+        path.to_string()
+    };
 
     // Insert the file into the table
-    let mut meta = ParMeta::new(path.to_string(), raw.pulsar_id)?;
+    let mut meta =
+        ParMeta::new(path.clone(), raw.pulsar_id, archivist.config())?;
     info!("Inserting ephemeride {path}");
 
-    // If auto resolve dupes is off, we just insert
-    if !archivist.config().behaviour.auto_resolve_duplicate_uploads {
-        meta.id = archivist.insert(meta.clone()).await?;
-        return Ok(meta);
-    }
-
-    // Otherwise, we check for pre-existing file
-    let existing = archivist
-        .find::<ParMeta>(&format!("checksum='{}'", meta.checksum))
-        .await?;
-
-    if let Some(pm) = existing {
+    if let Some(pm) = find_collision(archivist, &meta).await? {
         warn!(
             "Ephemeride with checksum {} already exists! Picking it instead.",
             pm.checksum,
         );
-        Ok(pm)
-    } else {
-        meta.id = archivist.insert(meta.clone()).await?;
-        Ok(meta)
+        return Ok(pm);
     }
+
+    meta.id = archivist.insert(meta.clone()).await?;
+    Ok(meta)
 }
 
 /// Parses `text` to load a `TemplateMeta`. This will try two things:
@@ -121,31 +123,122 @@ async fn template_from_file(
     path: &str,
 ) -> Result<TemplateMeta, ARPAError> {
     debug!("Picking template by path");
-    assert_exists(path)?;
+    assert_file_exists(path)?;
+
+    let path = if archivist.config().behaviour.archive_templates {
+        archive_upload(archivist, raw.pulsar_id, path, "templates").await?
+    } else {
+        path.to_string()
+    };
 
     // Insert the file into the table
     info!("Inserting new template {path}");
-    let mut meta = TemplateMeta::new(path.to_string(), raw.pulsar_id)?;
-
-    // If auto resolve dupes is off, we just insert
-    if !archivist.config().behaviour.auto_resolve_duplicate_uploads {
-        meta.id = archivist.insert(meta.clone()).await?;
-        return Ok(meta);
-    }
-
-    // Otherwise, we check for pre-existing file
-    let existing = archivist
-        .find::<TemplateMeta>(&format!("checksum='{}'", meta.checksum))
-        .await?;
+    let mut meta =
+        TemplateMeta::new(path.clone(), raw.pulsar_id, archivist.config())?;
 
-    if let Some(tm) = existing {
+    if let Some(tm) = find_collision(archivist, &meta).await? {
         warn!(
             "Template with checksum {} already exists! Picking it instead.",
             tm.checksum,
         );
-        Ok(tm)
-    } else {
-        meta.id = archivist.insert(meta.clone()).await?;
-        Ok(meta)
+        return Ok(tm);
+    }
+
+    meta.id = archivist.insert(meta.clone()).await?;
+    Ok(meta)
+}
+
+/// Looks for an entry colliding with the uploaded `meta`, telling apart a
+/// collision on content, i.e. the same file under another path, from one on
+/// path, i.e. a different file where one is already registered.
+///
+/// The former is fine to reuse if `behaviour.auto_resolve_duplicate_uploads`
+/// is set, and then the existing entry is returned. The latter is likely a
+/// mistake and always an error.
+async fn find_collision<T>(
+    archivist: &Archivist,
+    meta: &T,
+) -> Result<Option<T>, ARPAError>
+where
+    T: ChecksumItem,
+{
+    let path = meta.file_path();
+    let on_path = archivist
+        .find::<T>(&format!("file_path='{}'", path.replace('\'', "''")))
+        .await?;
+    if let Some(existing) = on_path
+        && existing.checksum() != meta.checksum()
+    {
+        return Err(ARPAError::DuplicatePath(
+            path.to_string(),
+            T::TABLE.to_string(),
+            existing.id(),
+        ));
+    }
+
+    let on_content = archivist
+        .find::<T>(&format!("checksum='{}'", meta.checksum()))
+        .await?;
+    match on_content {
+        Some(existing)
+            if archivist.config().behaviour.auto_resolve_duplicate_uploads =>
+        {
+            Ok(Some(existing))
+        }
+        Some(existing) => Err(ARPAError::DuplicateContent(
+            path.to_string(),
+            T::TABLE.to_string(),
+            existing.id(),
+        )),
+        None => Ok(None),
+    }
+}
+
+/// Puts an uploaded file in `{rawfile_storage}/{kind}/{PSR}/`, and returns
+/// its new path. If the same file is already there, that one is used.
+async fn archive_upload(
+    archivist: &Archivist,
+    pulsar_id: i32,
+    path: &str,
+    kind: &str,
+) -> Result<String, ARPAError> {
+    let config = archivist.config();
+    assert_dir_exists(&config.paths.rawfile_storage)?;
+
+    let pulsar = archivist.get::<PulsarMeta>(pulsar_id).await?;
+    let directory = format!(
+        "{}/{kind}/{}",
+        config.paths.rawfile_storage,
+        pulsar.alias.to_uppercase()
+    );
+    let name = path.rsplit('/').next().unwrap_or(path);
+
+    let (destination, collision) =
+        preview_archive_file(path, &directory, name)?;
+    match collision {
+        Collision::Absent => {
+            info!("Archiving {path}...");
+            let mut file_path = path.to_string();
+            archive_file(config, &mut file_path, &directory, name).await?;
+            Ok(file_path)
+        }
+        Collision::InPlace => Ok(destination),
+        Collision::PresentEqual
+            if compute_checksum(
+                path,
+                config.behaviour.checksum_algorithm,
+                &mut NoProgress,
+            )? == compute_checksum(
+                &destination,
+                config.behaviour.checksum_algorithm,
+                &mut NoProgress,
+            )? =>
+        {
+            warn!("{path} is already archived as {destination}.");
+            Ok(destination)
+        }
+        Collision::PresentEqual | Collision::PresentDifferent => {
+            Err(ARPAError::ArchiveCollision(destination))
+        }
     }
 }
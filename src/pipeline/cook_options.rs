@@ -0,0 +1,25 @@
+//! Per-run settings of the pipeline.
+
+use tokio_util::sync::CancellationToken;
+
+use crate::config::Preprocessing;
+
+/// Settings for a single [`super::cook`] run, on top of the config.
+#[derive(Debug, Clone, Default)]
+pub struct CookOptions {
+    /// Whether to run the diagnostics and make TOA plots.
+    pub diagnostics: bool,
+    /// How to scrunch the raw file, instead of the config's profile for its
+    /// obs system. Useful when e.g. a bright pulsar can be split up further
+    /// than the profile allows.
+    pub preprocessing: Option<Preprocessing>,
+    /// Whether to only run the tools and parse their output, without writing
+    /// anything to the database or moving any files. Use it with a raw file
+    /// from [`crate::data_types::RawMeta::parse_dry_run`] to check a backlog
+    /// before ingesting it.
+    pub dry_run: bool,
+    /// A token to stop the run with from elsewhere, e.g. when a user leaves.
+    /// It is checked between the steps of the pipeline, so a step that has
+    /// started still finishes.
+    pub cancel: Option<CancellationToken>,
+}
@@ -0,0 +1,44 @@
+//! The summary of a pipeline run.
+
+use std::time::Duration;
+
+use serde::Serialize;
+
+/// Everything a successful [`super::cook`] made, to persist or forward in
+/// one go.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CookReport {
+    /// The id of the raw file cooked.
+    pub raw_id: i32,
+    /// The id of the logged process.
+    pub process_id: i32,
+    /// The ids of the archived TOAs.
+    pub toa_ids: Vec<i32>,
+    /// The id of the archived processed file, if any.
+    pub processed_id: Option<i32>,
+    /// How each diagnostic went.
+    pub diagnostics: Vec<DiagnosticOutcome>,
+    /// The paths of all archived plots, both diagnostics and TOA plots.
+    pub plots: Vec<String>,
+    /// How many TOAs would have been archived, if this was a dry run.
+    pub dry_run_toas: Option<usize>,
+    /// How long each step took.
+    pub timings: Vec<(&'static str, Duration)>,
+    /// How long the whole run took.
+    pub duration: Duration,
+}
+
+/// How a diagnostic went.
+#[derive(Debug, Clone, Serialize)]
+pub struct DiagnosticOutcome {
+    /// The name of the diagnostic.
+    pub diagnostic: String,
+    /// The error, if it failed.
+    pub error: Option<String>,
+}
+impl DiagnosticOutcome {
+    /// Whether the diagnostic ran ok.
+    pub const fn passed(&self) -> bool {
+        self.error.is_none()
+    }
+}
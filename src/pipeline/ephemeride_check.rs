@@ -0,0 +1,131 @@
+//! Checking that an ephemeride fits a raw file before installing it.
+
+use std::{fs::File, io::BufReader};
+
+use log::warn;
+use psrutils::parfile::{FittedParameterValue, Parfile};
+
+use crate::{
+    ARPAError, Result,
+    config::{Config, EphemerideCheck},
+    conveniences::separation_arcsec,
+    data_types::{ParMeta, RawFileHeader},
+};
+
+/// Compares the ephemeride `par` to the header of the raw file at
+/// `raw_path`, without installing anything. Returns a description of every
+/// mismatch found, i.e. an empty list if they fit.
+///
+/// The pulsar names and positions are compared, the latter with the tolerance
+/// in `behaviour.ephemeride_tolerance`. If the ephemeride states the range of
+/// data it was fit on, the observation is also expected to fall within it.
+///
+/// # Errors
+/// Fails if the par file or the header can't be read.
+pub fn verify_ephemeride(
+    config: &Config,
+    par: &ParMeta,
+    raw_path: &str,
+) -> Result<Vec<String>> {
+    let parfile = Parfile::read(BufReader::new(File::open(&par.file_path)?))?;
+    let header = RawFileHeader::get_cached(config, raw_path)?;
+
+    let mut mismatches = Vec::new();
+
+    let name = parfile
+        .texts
+        .iter()
+        .find(|t| t.name() == "PSR")
+        .map(|t| t.value().as_str())
+        .unwrap_or_default();
+    if !same_name(name, &header.psr_name) {
+        mismatches.push(format!(
+            "the ephemeride is for {name}, but the file is of {}",
+            header.psr_name
+        ));
+    }
+
+    if let (Some(ra), Some(dec)) =
+        (value(parfile.ra.value()), value(parfile.dec.value()))
+    {
+        let separation = separation_arcsec(ra, dec, &header.ra, &header.dec);
+        let tolerance = config.behaviour.ephemeride_tolerance;
+        if separation > tolerance {
+            mismatches.push(format!(
+                "the positions differ by {separation:.1}\" \
+                (tolerance {tolerance}\")"
+            ));
+        }
+    }
+
+    let mjd = f64::from(header.date.int()) + header.date.frac();
+    let bound = |bound| {
+        parfile
+            .parameters
+            .iter()
+            .find(|p| p.name() == bound)
+            .and_then(|p| value(p.value()).copied())
+    };
+    if let Some(start) = bound("START")
+        && mjd < start
+    {
+        mismatches
+            .push(format!("the file (MJD {mjd}) is before START {start}"));
+    }
+    if let Some(finish) = bound("FINISH")
+        && mjd > finish
+    {
+        mismatches
+            .push(format!("the file (MJD {mjd}) is after FINISH {finish}"));
+    }
+
+    Ok(mismatches)
+}
+
+/// Runs [`verify_ephemeride`] as configured, warning about or refusing
+/// mismatches.
+pub(super) fn check_ephemeride(
+    config: &Config,
+    par: &ParMeta,
+    raw_path: &str,
+) -> Result<()> {
+    let check = config.behaviour.ephemeride_check;
+    if check == EphemerideCheck::Off {
+        return Ok(());
+    }
+
+    let mismatches = verify_ephemeride(config, par, raw_path)?;
+    if mismatches.is_empty() {
+        return Ok(());
+    }
+
+    let details = mismatches.join("; ");
+    if check == EphemerideCheck::Strict {
+        return Err(ARPAError::EphemerideMismatch(
+            par.file_path.clone(),
+            details,
+        ));
+    }
+
+    warn!("Ephemeride {} may not fit: {details}", par.file_path);
+    Ok(())
+}
+
+const fn value<T>(value: &FittedParameterValue<T>) -> Option<&T> {
+    match value {
+        FittedParameterValue::Missing => None,
+        FittedParameterValue::JustValue(value)
+        | FittedParameterValue::FitInfo { value, .. } => Some(value),
+    }
+}
+
+/// Compares pulsar names, ignoring case and a leading J or B on either.
+fn same_name(a: &str, b: &str) -> bool {
+    let strip = |name: &str| {
+        name.trim()
+            .trim_start_matches(['J', 'j', 'B', 'b'])
+            .to_lowercase()
+    };
+
+    strip(a) == strip(b)
+}
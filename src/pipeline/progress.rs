@@ -8,8 +8,9 @@ pub enum Status {
     #[default]
     Idle,
 
-    /// Some error ocurred in the process.
-    Error(String),
+    /// Some error ocurred in the process (with the directory where the
+    /// working files were kept, if any).
+    Error(String, Option<String>),
 
     /// The pipeline is just starting.
     Starting {
@@ -38,9 +39,16 @@ pub enum Status {
     /// Generating TOAs with `psrchive::pat`.
     GeneratingTOAs,
 
+    /// `psrchive::pat` made no plots, so they will be skipped.
+    SkippingTOAPlots,
+
     /// TOAs received (with count provided).
     GotTOAs(usize),
 
+    /// A dry run got this far, and would have archived the TOAs (with count
+    /// provided).
+    DryRun(usize),
+
     /// Logging `ProcessMeta` to DB.
     LoggingProcess,
 
@@ -50,6 +58,9 @@ pub enum Status {
     /// Successfully archived TOAs (with count provided).
     ArchivedTOAs(usize),
 
+    /// Archived the processed file (with its path provided).
+    ArchivedProcessed(String),
+
     /// Starts diagnosing (with count provided).
     Diagnosing(usize),
 
@@ -65,6 +76,9 @@ pub enum Status {
     /// passed provided).
     ArchivedTOAPlots(Option<usize>),
 
+    /// The pipeline was cancelled, and anything it archived rolled back.
+    Cancelled,
+
     /// The pipeline just finished (with total duration provided).
     Finished(std::time::Duration),
 }
@@ -73,7 +87,11 @@ impl std::fmt::Display for Status {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Idle => write!(f, "Idling..."),
-            Self::Error(err) => write!(f, "Encountered error: {err}"),
+            Self::Error(err, None) => write!(f, "Encountered error: {err}"),
+            Self::Error(err, Some(dir)) => write!(
+                f,
+                "Encountered error: {err} (working files kept in {dir})"
+            ),
 
             Self::Starting {
                 raw,
@@ -105,10 +123,19 @@ impl std::fmt::Display for Status {
             Self::Manipulating => write!(f, "Manipulating..."),
             Self::VerifyingTemplate => write!(f, "Verifying template..."),
             Self::GeneratingTOAs => write!(f, "Generating TOAs..."),
+            Self::SkippingTOAPlots => {
+                write!(f, "No plots from psrchive::pat, skipping them.")
+            }
             Self::GotTOAs(n) => write!(f, "Got {n} TOA(s)!"),
+            Self::DryRun(n) => {
+                write!(f, "Dry run: would have archived {n} TOA(s).")
+            }
             Self::LoggingProcess => write!(f, "Logging process..."),
             Self::ParsingTOAs => write!(f, "Parsing TOAs..."),
             Self::ArchivedTOAs(n) => write!(f, "Archived {n} TOA(s)!"),
+            Self::ArchivedProcessed(path) => {
+                write!(f, "Archived processed file as {path}.")
+            }
             Self::Diagnosing(n) => write!(f, "Running {n} diagnostic(s)..."),
 
             Self::FinishedDiagnostic { diagnostic, passed } => write!(
@@ -128,6 +155,8 @@ impl std::fmt::Display for Status {
                 write!(f, "Failed to archive plot(s) from psrchive::pat.")
             }
 
+            Self::Cancelled => write!(f, "Cancelled."),
+
             Self::Finished(dt) => {
                 write!(f, "Finished in {}!", display_elapsed_time(*dt))
             }
@@ -0,0 +1,66 @@
+//! The directory a single pipeline run works in.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::{info, warn};
+
+use crate::{ARPAError, config::Config, conveniences::assert_dir_exists};
+
+/// Where a run keeps its working files. This is either the shared temp dir,
+/// or, with `temp_dir_per_run`, a directory of its own inside it.
+pub(super) struct WorkDir {
+    path: String,
+    per_run: bool,
+}
+impl WorkDir {
+    /// Picks, and if need be creates, the directory for a run on raw file
+    /// `raw_id`.
+    pub(super) fn new(config: &Config, raw_id: i32) -> Result<Self, ARPAError> {
+        let temp_dir = &config.paths.temp_dir;
+        assert_dir_exists(temp_dir)?;
+
+        if !config.behaviour.temp_dir_per_run {
+            return Ok(Self {
+                path: temp_dir.clone(),
+                per_run: false,
+            });
+        }
+
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos());
+        let run_id = format!("run_{raw_id}_{nanos:x}");
+        let path = format!("{temp_dir}/{run_id}");
+        std::fs::create_dir(&path)?;
+        info!("Run {run_id} works in {path}");
+
+        Ok(Self {
+            path,
+            per_run: true,
+        })
+    }
+
+    /// The path of the directory.
+    pub(super) fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Removes the directory of a successful run.
+    pub(super) fn clean_up(self) {
+        if !self.per_run {
+            return;
+        }
+
+        if let Err(err) = std::fs::remove_dir_all(&self.path) {
+            warn!("Could not remove {}: {err}", self.path);
+        }
+    }
+
+    /// Keeps the directory of a failed run, returning its path.
+    pub(super) fn retain(self) -> Option<String> {
+        self.per_run.then(|| {
+            warn!("Keeping working files in {}", self.path);
+            self.path
+        })
+    }
+}
@@ -1,30 +1,261 @@
-use log::info;
-use tokio::process::Command;
+//! A background runner, taking jobs from a queue and running them one at a
+//! time on its own [`Archivist`].
+//!
+//! This is for running `arpa` as a service: spawn a [`Worker`], and
+//! [`WorkerHandle::enqueue`] jobs as they come in.
 
-use crate::Result;
+use std::{
+    ops::ControlFlow,
+    panic::{AssertUnwindSafe, resume_unwind},
+    sync::Arc,
+    time::Instant,
+};
 
-pub struct Worker {
+use futures_util::FutureExt;
+use log::{error, info};
+use tokio::{
+    sync::{mpsc, oneshot},
+    task::JoinHandle,
+};
+
+use crate::{
+    ARPAError, Archivist, Result,
+    conveniences::panic_message,
+    fitting::{Residual, fitter},
+    pipeline::{
+        CookOptions, CookReport, Status, cook, parse_input_ephemeride,
+        parse_input_raw, parse_input_template,
+    },
+};
+
+/// The status callback of a worker, getting the id of the job as well.
+type StatusCallback = Arc<dyn Fn(u64, Status) -> ControlFlow<()> + Send + Sync>;
+
+/// A job for a [`Worker`].
+#[derive(Debug, Clone)]
+pub enum Job {
+    /// Runs [`cook`]. The raw file, ephemeride, and template are given as
+    /// either ids or paths, as for the `parse_input_` functions.
+    Cook {
+        /// The raw file.
+        raw: String,
+        /// The ephemeride to install, if any.
+        ephemeride: Option<String>,
+        /// The template.
+        template: String,
+        /// The options of the run.
+        options: CookOptions,
+    },
+    /// Fits a timing model with the configured backend.
+    Fit {
+        /// The par file.
+        par_file: String,
+        /// The tim file.
+        tim_file: String,
+    },
+}
+
+/// What a finished [`Job`] made.
+#[derive(Debug, Clone)]
+pub enum JobOutcome {
+    /// The report of a cook job.
+    Cooked(CookReport),
+    /// The post-fit residuals of a fit job.
+    Fitted(Vec<Residual>),
+}
+
+/// A queued job, to wait for its outcome with.
+#[derive(Debug)]
+pub struct JobTicket {
+    id: u64,
+    outcome: oneshot::Receiver<Result<JobOutcome>>,
+}
+impl JobTicket {
+    /// The id of the job, as given to the status callback.
+    pub const fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Waits for the job to finish.
+    ///
+    /// # Errors
+    /// Fails if the job fails, or the worker stopped before running it.
+    pub async fn outcome(self) -> Result<JobOutcome> {
+        self.outcome.await.map_err(|_| ARPAError::WorkerStopped)?
+    }
+}
 
+/// Runs queued jobs one at a time in the background.
+pub struct Worker {
+    archivist: Archivist,
+    status_callback: StatusCallback,
+    queue: mpsc::UnboundedReceiver<Queued>,
 }
+
+/// A job in the queue, with its id and where its outcome goes.
+type Queued = (u64, Job, oneshot::Sender<Result<JobOutcome>>);
+
 impl Worker {
-    pub fn new() -> Self {
-        Self {  }
+    /// Starts a worker in the background, taking over `archivist`. The
+    /// `status_callback` gets the progress of every job, along with its id,
+    /// and may cancel a running cook as with [`cook`].
+    pub fn spawn(
+        archivist: Archivist,
+        status_callback: impl Fn(u64, Status) -> ControlFlow<()>
+        + Send
+        + Sync
+        + 'static,
+    ) -> WorkerHandle {
+        let (sender, queue) = mpsc::unbounded_channel();
+        let worker = Self {
+            archivist,
+            status_callback: Arc::new(status_callback),
+            queue,
+        };
+
+        WorkerHandle {
+            sender,
+            next_id: 0,
+            task: tokio::spawn(worker.run()),
+        }
+    }
+
+    /// Takes jobs until the queue is closed, and then gives the archivist
+    /// back.
+    async fn run(mut self) -> Archivist {
+        while let Some((id, job, outcome)) = self.queue.recv().await {
+            info!("Starting job {id}...");
+            // A panicking job must not take the worker, and with it the
+            // archivist, down with it
+            let result = match AssertUnwindSafe(self.run_job(id, job))
+                .catch_unwind()
+                .await
+            {
+                Ok(result) => result,
+                Err(payload) => {
+                    let err = ARPAError::JobPanicked(
+                        id,
+                        panic_message(payload.as_ref()),
+                    );
+                    // It may have left a transaction open, and if not there
+                    // is nothing to roll back
+                    _ = self.archivist.rollback_transaction().await;
+                    _ = (self.status_callback)(
+                        id,
+                        Status::Error(err.to_string(), None),
+                    );
+                    Err(err)
+                }
+            };
+            if let Err(err) = &result {
+                error!("Job {id} failed: {err}");
+            }
+
+            // Nobody may be waiting for it, which is fine
+            _ = outcome.send(result);
+        }
+
+        self.archivist
+    }
+
+    async fn run_job(&mut self, id: u64, job: Job) -> Result<JobOutcome> {
+        let callback = Arc::clone(&self.status_callback);
+        let report = move |status| callback(id, status);
+
+        match job {
+            Job::Cook {
+                raw,
+                ephemeride,
+                template,
+                options,
+            } => {
+                let archivist = &mut self.archivist;
+                let inputs = async {
+                    let raw = parse_input_raw(archivist, &raw).await?;
+                    let ephemeride = match ephemeride {
+                        Some(text) => Some(
+                            parse_input_ephemeride(archivist, &raw, &text)
+                                .await?,
+                        ),
+                        None => None,
+                    };
+                    let template =
+                        parse_input_template(archivist, &raw, &template)
+                            .await?;
+                    Ok::<_, ARPAError>((raw, ephemeride, template))
+                }
+                .await;
+
+                let (raw, ephemeride, template) =
+                    inputs.inspect_err(|err| {
+                        _ = report(Status::Error(err.to_string(), None));
+                    })?;
+
+                cook(archivist, raw, ephemeride, template, &options, report)
+                    .await
+                    .map(JobOutcome::Cooked)
+            }
+            Job::Fit { par_file, tim_file } => {
+                let start = Instant::now();
+                // The backends wait on their tools, so they stay off the
+                // runtime
+                let config = self.archivist.shared_config();
+                let fitted = tokio::task::spawn_blocking(move || {
+                    fitter(&config).fit(&par_file, &tim_file)
+                })
+                .await
+                .unwrap_or_else(|err| {
+                    match err.try_into_panic() {
+                        Ok(payload) => resume_unwind(payload),
+                        Err(err) => Err(ARPAError::JoinThread(err.to_string())),
+                    }
+                });
+                match fitted {
+                    Ok(residuals) => {
+                        _ = report(Status::Finished(start.elapsed()));
+                        Ok(JobOutcome::Fitted(residuals))
+                    }
+                    Err(err) => {
+                        _ = report(Status::Error(err.to_string(), None));
+                        Err(err)
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A handle to a running [`Worker`].
+#[derive(Debug)]
+pub struct WorkerHandle {
+    sender: mpsc::UnboundedSender<Queued>,
+    next_id: u64,
+    task: JoinHandle<Archivist>,
+}
+impl WorkerHandle {
+    /// Puts `job` at the end of the queue.
+    ///
+    /// # Errors
+    /// Fails if the worker has stopped, e.g. because its runtime shut down.
+    pub fn enqueue(&mut self, job: Job) -> Result<JobTicket> {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let (sender, outcome) = oneshot::channel();
+        self.sender
+            .send((id, job, sender))
+            .map_err(|_| ARPAError::WorkerStopped)?;
+
+        Ok(JobTicket { id, outcome })
     }
 
-    pub async fn tempo2_fit(
-        &self,
-        par_file: &str,
-        tim_file: &str,
-    ) -> Result<()> {
-        let result = Command::new("tempo2")
-            .arg("-f")
-            .arg(par_file)
-            .arg(tim_file)
-            .status()
-            .await?;
-
-        info!("{}", result);
-
-        Ok(())
+    /// Lets the worker finish the jobs already queued, and then gives its
+    /// archivist back.
+    ///
+    /// # Errors
+    /// Fails if the task of the worker was aborted.
+    pub async fn shutdown(self) -> Result<Archivist> {
+        drop(self.sender);
+        Ok(self.task.await?)
     }
 }